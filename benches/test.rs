@@ -3,7 +3,10 @@ extern crate bencher;
 
 //use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
 use bencher::Bencher;
-use libts::pitch::{from_bytes as pitch_bytes, Message};
+use libts::pitch::{
+    from_bytes as pitch_bytes, to_bytes as pitch_to_bytes, AddOrder, Body, CancelReason, Message,
+    OrderDelete, PitchBatch, Side, Trade,
+};
 use libts::{from_bytes, from_msg, ClMessage, SysClock, UnixTime};
 use serde::Deserialize;
 
@@ -83,6 +86,57 @@ fn from_bytes_add_order(bench: &mut Bencher) {
     })
 }
 
+// 1k mixed AddOrder, Trade and OrderDelete messages
+fn pitch_msgs_1k() -> Vec<ClMessage> {
+    (0..1000u32)
+        .map(|i| {
+            let body = match i % 3 {
+                0 => Body::AddOrder(AddOrder {
+                    reference: i as u64,
+                    side: Side::Buy,
+                    qty: 100,
+                    price: 51050,
+                }),
+                1 => Body::Trade(Trade {
+                    reference: i as u64,
+                    side: Side::Sell,
+                    qty: 10,
+                    price: 51050,
+                    match_no: i as u64,
+                }),
+                _ => Body::OrderDelete(OrderDelete {
+                    reason: CancelReason::ByUser,
+                    reference: i as u64,
+                }),
+            };
+            let msg = Message {
+                index: (i % 16) as u16,
+                tracking: i as u16,
+                timestamp: i * 100,
+                body,
+            };
+            ClMessage::new(&pitch_to_bytes(&msg).unwrap())
+        })
+        .collect()
+}
+
+fn from_bytes_pitch_1k(bench: &mut Bencher) {
+    let msgs = pitch_msgs_1k();
+    bench.iter(|| {
+        for m in &msgs {
+            let _msg: Message = pitch_bytes(m.data()).unwrap();
+        }
+    })
+}
+
+fn batch_decode_pitch_1k(bench: &mut Bencher) {
+    let msgs = pitch_msgs_1k();
+    let mut batch = PitchBatch::new();
+    bench.iter(|| {
+        assert_eq!(batch.decode(&msgs), 1000);
+    })
+}
+
 benchmark_group!(
     benches,
     timeval_date_1k,
@@ -91,5 +145,7 @@ benchmark_group!(
     from_msg_struct1,
     from_bytes_struct,
     from_bytes_add_order,
+    from_bytes_pitch_1k,
+    batch_decode_pitch_1k,
 );
 benchmark_main!(benches);
@@ -7,9 +7,13 @@ pub mod mdcache;
 pub mod measure;
 mod mmap;
 mod msg;
+pub mod net;
 pub mod pitch;
 mod price_type;
+pub mod replay;
 mod serde;
+#[cfg(test)]
+mod testutil;
 mod timestamp;
 pub mod u64;
 mod unix_time;
@@ -0,0 +1,271 @@
+//! archive - compressed on-disk copy of a series
+//!
+//! An archive is a 16 bytes file header, the MdHeader of the series and
+//! blocks of up to `block_msgs` records. Each record is packed as u8
+//! length and its bytes, each block is LZ compressed, or stored if that
+//! does not pay off, with CRC32 of its packed records.
+
+use super::lz::{compress, crc32, decompress};
+use super::{MdError, MdHeader, Result, Series, MD_HEADER_LEN};
+use crate::ClMessage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const ARCHIVE_MAGIC: u32 = u32::from_le_bytes(*b"TSAR");
+const ARCHIVE_VERSION: u32 = 1;
+pub const ARCHIVE_BLOCK_MSGS: usize = 4096;
+const FILE_HDR_LEN: usize = 16;
+const BLOCK_HDR_LEN: usize = 16;
+
+/// Series loaded from archive file
+pub struct Archive {
+    path: String,
+    header: MdHeader,
+    msgs: Vec<ClMessage>,
+}
+
+impl Archive {
+    /// Write messages published of series to fpath, return bytes written
+    pub fn write<S: Series + ?Sized>(md: &S, fpath: &str) -> Result<u64> {
+        Archive::write_blocks(md, fpath, ARCHIVE_BLOCK_MSGS)
+    }
+    pub fn write_blocks<S: Series + ?Sized>(md: &S, fpath: &str, block_msgs: usize) -> Result<u64> {
+        let block_msgs = block_msgs.max(1);
+        let msgs = md.published();
+        let mut header = *md.header();
+        header.cnt_messages = msgs.len() as u64;
+        let nblocks = msgs.len().div_ceil(block_msgs);
+        let mut wr = BufWriter::new(File::create(fpath)?);
+        let mut fhdr = Vec::with_capacity(FILE_HDR_LEN + MD_HEADER_LEN as usize);
+        for v in [
+            ARCHIVE_MAGIC,
+            ARCHIVE_VERSION,
+            block_msgs as u32,
+            nblocks as u32,
+        ] {
+            fhdr.extend_from_slice(&v.to_le_bytes());
+        }
+        let hdr = unsafe {
+            std::slice::from_raw_parts(
+                &header as *const MdHeader as *const u8,
+                MD_HEADER_LEN as usize,
+            )
+        };
+        fhdr.extend_from_slice(hdr);
+        wr.write_all(&fhdr)?;
+        let mut written = fhdr.len() as u64;
+        let mut raw = Vec::with_capacity(block_msgs * 64);
+        let mut comp = Vec::with_capacity(block_msgs * 64);
+        for blk in msgs.chunks(block_msgs) {
+            raw.clear();
+            comp.clear();
+            for m in blk {
+                raw.push(m.len() as u8);
+                raw.extend_from_slice(m.data());
+            }
+            compress(&raw, &mut comp);
+            // comp_len 0 marks block stored as is
+            let (comp_len, data) = if comp.len() < raw.len() {
+                (comp.len() as u32, &comp[..])
+            } else {
+                (0, &raw[..])
+            };
+            for v in [blk.len() as u32, raw.len() as u32, comp_len, crc32(&raw)] {
+                wr.write_all(&v.to_le_bytes())?;
+            }
+            wr.write_all(data)?;
+            written += (BLOCK_HDR_LEN + data.len()) as u64;
+        }
+        wr.flush()?;
+        Ok(written)
+    }
+    /// Read archive, decompress and verify all blocks
+    pub fn load(fpath: &str) -> Result<Archive> {
+        let buf = std::fs::read(fpath)?;
+        let file_len = buf.len() as u64;
+        let hdr_len = FILE_HDR_LEN + MD_HEADER_LEN as usize;
+        let truncated = |md_len: usize| MdError::Truncated {
+            md_len: md_len as u64,
+            file_len,
+        };
+        if buf.len() < hdr_len {
+            return Err(truncated(hdr_len));
+        }
+        let u32_at = |off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+        if u32_at(0) != ARCHIVE_MAGIC {
+            return Err(MdError::BadMagic(u32_at(0)));
+        }
+        if u32_at(4) != ARCHIVE_VERSION {
+            return Err(MdError::UnsupportedVersion(u32_at(4)));
+        }
+        let (block_msgs, nblocks) = (u32_at(8) as usize, u32_at(12) as usize);
+        // a record packs to u8 length and at most 62 bytes
+        let max_rec = 1 + ClMessage::default().cap();
+        let header =
+            unsafe { std::ptr::read_unaligned(buf[FILE_HDR_LEN..].as_ptr() as *const MdHeader) };
+        if header.cnt_messages > header.max_messages {
+            return Err(MdError::Count {
+                cnt_messages: header.cnt_messages,
+                max_messages: header.max_messages,
+            });
+        }
+        // header is not trusted for capacity, each block takes its header
+        let max_blocks = nblocks.min((buf.len() - hdr_len) / BLOCK_HDR_LEN);
+        let mut msgs =
+            Vec::with_capacity((header.cnt_messages as usize).min(max_blocks * block_msgs));
+        let mut off = hdr_len;
+        for blk in 0..nblocks {
+            if buf.len() < off + BLOCK_HDR_LEN {
+                return Err(truncated(off + BLOCK_HDR_LEN));
+            }
+            let (nmsg, raw_len) = (u32_at(off) as usize, u32_at(off + 4) as usize);
+            let (comp_len, crc) = (u32_at(off + 8) as usize, u32_at(off + 12));
+            off += BLOCK_HDR_LEN;
+            // raw_len decides allocation, bound it by records of block and
+            // by 255 bytes out per compressed byte at most
+            if nmsg > block_msgs
+                || raw_len > nmsg * max_rec
+                || (comp_len != 0 && raw_len > comp_len.saturating_mul(255))
+            {
+                return Err(MdError::Corrupt(blk));
+            }
+            let dlen = if comp_len == 0 { raw_len } else { comp_len };
+            if buf.len() < off + dlen {
+                return Err(truncated(off + dlen));
+            }
+            let data = &buf[off..off + dlen];
+            off += dlen;
+            let dec;
+            let raw = if comp_len == 0 {
+                data
+            } else {
+                dec = decompress(data, raw_len).ok_or(MdError::Corrupt(blk))?;
+                &dec[..]
+            };
+            if crc32(raw) != crc {
+                return Err(MdError::Checksum(blk));
+            }
+            let mut p = 0;
+            for _ in 0..nmsg {
+                let ll = *raw.get(p).ok_or(MdError::Corrupt(blk))? as usize;
+                let rec = raw.get(p + 1..p + 1 + ll).ok_or(MdError::Corrupt(blk))?;
+                msgs.push(ClMessage::new(rec));
+                p += 1 + ll;
+            }
+            if p != raw.len() {
+                return Err(MdError::Corrupt(blk));
+            }
+        }
+        if msgs.len() as u64 != header.cnt_messages {
+            return Err(MdError::Count {
+                cnt_messages: header.cnt_messages,
+                max_messages: msgs.len() as u64,
+            });
+        }
+        Ok(Archive {
+            path: fpath.to_owned(),
+            header,
+            msgs,
+        })
+    }
+}
+
+impl Series for Archive {
+    fn header(&self) -> &MdHeader {
+        &self.header
+    }
+    fn published(&self) -> &[ClMessage] {
+        &self.msgs
+    }
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdcache::{
+        iidx_path, tidx_path, InstrumentIndex, MdCache, MdCacheWriter, TimeIndex,
+    };
+    use crate::pitch::{from_bytes, to_bytes, AddOrder, Body, Message, Side};
+    use crate::testutil::test_series_name;
+
+    #[test]
+    fn test_archive() {
+        let name = &test_series_name("archive_test");
+        let mut wr = MdCacheWriter::create(name, 20_000, 2).unwrap();
+        for i in 0..10_000u32 {
+            let msg = Message {
+                index: (i % 7) as u16,
+                tracking: (i / 7) as u16,
+                timestamp: i * 100,
+                body: Body::AddOrder(AddOrder {
+                    reference: i as u64,
+                    side: Side::Sell,
+                    qty: 10,
+                    price: 4000 + (i % 13) as i32,
+                }),
+            };
+            wr.append(&to_bytes(&msg).unwrap()).unwrap();
+        }
+        wr.append(b"").unwrap();
+        let md = MdCache::open(name).unwrap();
+        let fpath = &format!("/tmp/archive_test_{}.tsar", std::process::id());
+        let ll = Archive::write(&md, fpath).unwrap();
+        println!("archive {} msgs -> {} bytes", md.len(), ll);
+        assert!(ll < (md.len() * 64 / 4) as u64);
+        let ar = Archive::load(fpath).unwrap();
+        assert_eq!(ar.len(), 10_001);
+        assert_eq!(ar.header().session_no, 2);
+        assert_eq!(ar.header().max_messages, 20_000);
+        assert!(ar.published() == md.published());
+        assert_eq!(from_bytes(ar.published()[77].data()).unwrap().tracking, 11);
+        // indexes work over archive the same
+        let tidx = TimeIndex::build(ar.published(), 0, 256);
+        assert_eq!(tidx.count(), 10_001);
+        let series: &dyn Series = &ar;
+        let tidx = TimeIndex::load_or_build(series, 256).unwrap();
+        assert_eq!(tidx.count(), 10_001);
+        let iidx = InstrumentIndex::load_or_build(&ar).unwrap();
+        assert_eq!(iidx.positions(3).len(), 1_429);
+
+        let orig = std::fs::read(fpath).unwrap();
+        // cnt_messages of MdHeader at 24
+        let mut buf = orig.clone();
+        buf[FILE_HDR_LEN + 24..FILE_HDR_LEN + 32].copy_from_slice(&10_000u64.to_le_bytes());
+        std::fs::write(fpath, &buf).unwrap();
+        assert!(matches!(
+            Archive::load(fpath),
+            Err(MdError::Count {
+                cnt_messages: 10_000,
+                max_messages: 10_001
+            })
+        ));
+        // raw_len of first block beyond its records packed
+        let off = FILE_HDR_LEN + MD_HEADER_LEN as usize + 4;
+        for raw_len in [u32::MAX, 4096 * 63 + 1] {
+            let mut buf = orig.clone();
+            buf[off..off + 4].copy_from_slice(&raw_len.to_le_bytes());
+            std::fs::write(fpath, &buf).unwrap();
+            assert!(matches!(Archive::load(fpath), Err(MdError::Corrupt(0))));
+        }
+
+        let mut buf = orig;
+        let pos = buf.len() - 10;
+        buf[pos] ^= 0x55;
+        std::fs::write(fpath, &buf).unwrap();
+        let err = Archive::load(fpath).err().unwrap();
+        println!("corrupted archive: {}", err);
+        assert!(matches!(err, MdError::Checksum(2) | MdError::Corrupt(2)));
+        std::fs::write(fpath, &buf[..100]).unwrap();
+        assert!(matches!(
+            Archive::load(fpath),
+            Err(MdError::Truncated { .. })
+        ));
+        for fp in [fpath.to_owned(), tidx_path(fpath), iidx_path(fpath)] {
+            _ = std::fs::remove_file(fp);
+        }
+        _ = std::fs::remove_file(wr.path());
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum MdError {
+    Io(io::Error),
+    /// magic is neither `MD_MAGIC` nor 0 of legacy writer
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    /// rec_size differs from size of ClMessage
+    RecSize(i32),
+    /// md_len too short for max_messages records
+    Layout {
+        max_messages: u64,
+        md_len: u64,
+    },
+    /// file shorter than md_len
+    Truncated {
+        md_len: u64,
+        file_len: u64,
+    },
+    /// cnt_messages beyond max_messages, or beyond records of archive
+    Count {
+        cnt_messages: u64,
+        max_messages: u64,
+    },
+    MapFailed(String),
+    Full,
+    /// message longer than a record holds
+    TooLong(usize),
+    /// CRC32 mismatch of archive block
+    Checksum(usize),
+    /// archive block failed to decompress or unpack
+    Corrupt(usize),
+    /// positions of instrument out of order or beyond messages indexed
+    BadPositions(u16),
+}
+
+pub type Result<T> = std::result::Result<T, MdError>;
+
+impl fmt::Display for MdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MdError::Io(e) => write!(f, "series io: {}", e),
+            MdError::BadMagic(m) => write!(f, "bad series magic {:#010x}", m),
+            MdError::UnsupportedVersion(v) => write!(f, "unsupported series version {}", v),
+            MdError::RecSize(sz) => write!(f, "record size {} mismatch", sz),
+            MdError::Layout {
+                max_messages,
+                md_len,
+            } => write!(
+                f,
+                "md_len {} too short for {} records",
+                md_len, max_messages
+            ),
+            MdError::Truncated { md_len, file_len } => {
+                write!(
+                    f,
+                    "series truncated, file {} of md_len {}",
+                    file_len, md_len
+                )
+            }
+            MdError::Count {
+                cnt_messages,
+                max_messages,
+            } => write!(f, "cnt_messages {} beyond {}", cnt_messages, max_messages),
+            MdError::MapFailed(p) => write!(f, "mmap {} failed", p),
+            MdError::Full => f.write_str("series full"),
+            MdError::TooLong(ll) => write!(f, "message of {} bytes too long", ll),
+            MdError::Checksum(blk) => write!(f, "checksum mismatch of block {}", blk),
+            MdError::Corrupt(blk) => write!(f, "block {} corrupted", blk),
+            MdError::BadPositions(idx) => write!(f, "bad positions of instrument {}", idx),
+        }
+    }
+}
+
+impl std::error::Error for MdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MdError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MdError {
+    fn from(e: io::Error) -> MdError {
+        MdError::Io(e)
+    }
+}
@@ -0,0 +1,297 @@
+//! iidx - per instrument position index of a series
+//!
+//! Lists the record positions of each PITCH instrument `index`, built
+//! offline from messages published or kept up to date by the writer,
+//! saved beside the series as `.iidx`.
+
+use super::{MdError, MdHeader, Result, Series};
+use crate::pitch::peek_header;
+use crate::ClMessage;
+use std::collections::HashMap;
+
+const IIDX_MAGIC: u32 = u32::from_le_bytes(*b"TSII");
+const IIDX_VERSION: u32 = 2;
+const IIDX_HEADER_LEN: usize = 40;
+
+/// Index file path of series file
+pub fn iidx_path(series_path: &str) -> String {
+    series_path.to_owned() + ".iidx"
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstrumentIndex {
+    count: u64,
+    // identity of series indexed, from its MdHeader
+    init_time: i64,
+    session_no: i32,
+    positions: HashMap<u16, Vec<u64>>,
+}
+
+impl InstrumentIndex {
+    pub fn new() -> InstrumentIndex {
+        Default::default()
+    }
+    pub fn build(msgs: &[ClMessage]) -> InstrumentIndex {
+        let mut iidx = InstrumentIndex::new();
+        iidx.update(msgs);
+        iidx
+    }
+    /// Mark index as of series of md
+    pub(super) fn of_series(mut self, md: &MdHeader) -> InstrumentIndex {
+        self.init_time = md.init_time;
+        self.session_no = md.session_no;
+        self
+    }
+    /// Index messages after those covered, return messages added
+    pub fn update(&mut self, msgs: &[ClMessage]) -> usize {
+        let start = self.count as usize;
+        if start >= msgs.len() {
+            return 0;
+        }
+        for m in &msgs[start..] {
+            self.push(m.data());
+        }
+        msgs.len() - start
+    }
+    /// Index next message of series
+    pub fn push(&mut self, buf: &[u8]) {
+        if let Some((index, _, _)) = peek_header(buf) {
+            self.positions.entry(index).or_default().push(self.count);
+        }
+        self.count += 1;
+    }
+    /// Load index saved beside series of md if it is of the same series
+    /// and index messages published since, save it if updated
+    pub fn load_or_build<S: Series + ?Sized>(md: &S) -> Result<InstrumentIndex> {
+        let fpath = iidx_path(md.path());
+        let msgs = md.published();
+        let hdr = md.header();
+        let mut iidx = match InstrumentIndex::load(&fpath) {
+            Ok(iidx)
+                if iidx.count <= msgs.len() as u64
+                    && iidx.init_time == hdr.init_time
+                    && iidx.session_no == hdr.session_no =>
+            {
+                iidx
+            }
+            _ => InstrumentIndex::new().of_series(hdr),
+        };
+        if iidx.update(msgs) > 0 || iidx.count == 0 {
+            iidx.save(&fpath)?;
+        }
+        Ok(iidx)
+    }
+    /// Messages covered
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Positions of messages of instrument index
+    pub fn positions(&self, index: u16) -> &[u64] {
+        match self.positions.get(&index) {
+            Some(v) => v,
+            None => &[],
+        }
+    }
+    /// Instruments indexed, unordered
+    pub fn instruments(&self) -> impl Iterator<Item = u16> + '_ {
+        self.positions.keys().copied()
+    }
+    /// Messages of instruments in series order
+    pub fn select<'i, 'm>(&'i self, msgs: &'m [ClMessage], indexes: &[u16]) -> Select<'i, 'm> {
+        self.select_from(msgs, indexes, 0)
+    }
+    /// Messages of instruments in series order from position start, e.g.
+    /// found by `TimeIndex::seek_time`
+    pub fn select_from<'i, 'm>(
+        &'i self,
+        msgs: &'m [ClMessage],
+        indexes: &[u16],
+        start: usize,
+    ) -> Select<'i, 'm> {
+        let mut lists: Vec<&'i [u64]> = Vec::with_capacity(indexes.len());
+        for idx in indexes {
+            let pos = self.positions(*idx);
+            let pos = &pos[pos.partition_point(|p| *p < start as u64)..];
+            if !pos.is_empty() && !lists.iter().any(|l| l.as_ptr() == pos.as_ptr()) {
+                lists.push(pos);
+            }
+        }
+        Select { msgs, lists }
+    }
+    pub fn save(&self, fpath: &str) -> Result<()> {
+        let npos: usize = self.positions.values().map(|v| v.len()).sum();
+        let ninst = self.positions.len();
+        let mut buf = Vec::with_capacity(IIDX_HEADER_LEN + ninst * 16 + npos * 8);
+        for v in [IIDX_MAGIC, IIDX_VERSION, self.session_no as u32, 0] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [self.count, ninst as u64, self.init_time as u64] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut keys: Vec<&u16> = self.positions.keys().collect();
+        keys.sort();
+        for k in keys {
+            let pos = &self.positions[k];
+            for v in [*k as u64, pos.len() as u64] {
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            for p in pos {
+                buf.extend_from_slice(&p.to_le_bytes());
+            }
+        }
+        std::fs::write(fpath, buf)?;
+        Ok(())
+    }
+    pub fn load(fpath: &str) -> Result<InstrumentIndex> {
+        let buf = std::fs::read(fpath)?;
+        let file_len = buf.len() as u64;
+        let truncated = MdError::Truncated {
+            md_len: IIDX_HEADER_LEN as u64,
+            file_len,
+        };
+        if buf.len() < IIDX_HEADER_LEN {
+            return Err(truncated);
+        }
+        let u32_at = |off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+        let u64_at = |off: usize| u64::from_le_bytes(buf[off..off + 8].try_into().unwrap());
+        if u32_at(0) != IIDX_MAGIC {
+            return Err(MdError::BadMagic(u32_at(0)));
+        }
+        if u32_at(4) != IIDX_VERSION {
+            return Err(MdError::UnsupportedVersion(u32_at(4)));
+        }
+        let (count, ninst) = (u64_at(16), u64_at(24));
+        let (init_time, session_no) = (u64_at(32) as i64, u32_at(8) as i32);
+        let mut positions = HashMap::new();
+        let mut off = IIDX_HEADER_LEN;
+        for _ in 0..ninst {
+            if buf.len() < off + 16 {
+                return Err(truncated);
+            }
+            let (k, n) = (u64_at(off) as u16, u64_at(off + 8) as usize);
+            off += 16;
+            match n.checked_mul(8).and_then(|v| v.checked_add(off)) {
+                Some(end) if end <= buf.len() => (),
+                _ => return Err(truncated),
+            }
+            let pos: Vec<u64> = (0..n).map(|i| u64_at(off + i * 8)).collect();
+            // Select merges ascending positions within messages indexed
+            if pos.windows(2).any(|w| w[0] >= w[1]) || pos.last().is_some_and(|p| *p >= count) {
+                return Err(MdError::BadPositions(k));
+            }
+            off += n * 8;
+            positions.insert(k, pos);
+        }
+        if off != buf.len() {
+            return Err(truncated);
+        }
+        Ok(InstrumentIndex {
+            count,
+            init_time,
+            session_no,
+            positions,
+        })
+    }
+}
+
+/// Iterator over (position, message) of selected instruments
+pub struct Select<'i, 'm> {
+    msgs: &'m [ClMessage],
+    lists: Vec<&'i [u64]>,
+}
+
+impl<'i, 'm> Iterator for Select<'i, 'm> {
+    type Item = (usize, &'m ClMessage);
+    fn next(&mut self) -> Option<Self::Item> {
+        // few instruments selected, linear pick of the lowest head
+        let (i, _) = self.lists.iter().enumerate().min_by_key(|(_, l)| l[0])?;
+        let pos = self.lists[i][0] as usize;
+        self.lists[i] = &self.lists[i][1..];
+        if self.lists[i].is_empty() {
+            self.lists.swap_remove(i);
+        }
+        let msg = self.msgs.get(pos)?;
+        Some((pos, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdcache::{MdCache, MdCacheWriter, TimeIndex};
+    use crate::testutil::{add_orders, test_series_name};
+
+    fn series(cnt: u32) -> Vec<ClMessage> {
+        add_orders(0..cnt, |i| ((i % 5) as u16, (i / 5) as u16, i * 1000))
+    }
+
+    #[test]
+    fn test_instrument_index() {
+        let msgs = series(1000);
+        let mut iidx = InstrumentIndex::build(&msgs[..500]);
+        assert_eq!(iidx.count(), 500);
+        assert_eq!(iidx.update(&msgs), 500);
+        assert_eq!(iidx.instruments().count(), 5);
+        assert_eq!(iidx.positions(3).len(), 200);
+        assert_eq!(iidx.positions(3)[1], 8);
+        assert!(iidx.positions(9).is_empty());
+        let sel: Vec<usize> = iidx.select(&msgs, &[4, 1, 4]).map(|(p, _)| p).collect();
+        assert_eq!(sel.len(), 400);
+        assert_eq!(&sel[..4], &[1, 4, 6, 9]);
+        assert!(sel.windows(2).all(|w| w[0] < w[1]));
+        let tidx = TimeIndex::build(&msgs, 0, 64);
+        let start = tidx.seek_micros(&msgs, 900_000);
+        let mut sel = iidx.select_from(&msgs, &[2], start);
+        let (pos, msg) = sel.next().unwrap();
+        assert_eq!(pos, 902);
+        assert_eq!(peek_header(msg.data()).unwrap().0, 2);
+        assert_eq!(sel.count(), 19);
+    }
+
+    #[test]
+    fn test_instrument_index_writer() {
+        let msgs = series(300);
+        let name = &test_series_name("iidx_test");
+        let mut wr = MdCacheWriter::create(name, 300, 1).unwrap();
+        for m in &msgs[..100] {
+            wr.push(m).unwrap();
+        }
+        wr.enable_instrument_index();
+        for m in &msgs[100..] {
+            wr.push(m).unwrap();
+        }
+        let built = InstrumentIndex::build(&msgs).of_series(wr.header());
+        assert_eq!(wr.instrument_index(), Some(&built));
+        let fpath = iidx_path(wr.path());
+        let spath = wr.path().to_owned();
+        wr.close().unwrap();
+        assert_eq!(InstrumentIndex::load(&fpath).unwrap(), built);
+        let md = MdCache::open(name).unwrap();
+        assert_eq!(InstrumentIndex::load_or_build(&md).unwrap(), built);
+        // index of another series is rebuilt
+        let other = InstrumentIndex {
+            session_no: 2,
+            ..InstrumentIndex::build(&msgs[..10])
+        };
+        other.save(&fpath).unwrap();
+        assert_eq!(InstrumentIndex::load_or_build(&md).unwrap(), built);
+        let mut bad = built.clone();
+        bad.positions.get_mut(&3).unwrap().swap(0, 1);
+        bad.save(&fpath).unwrap();
+        assert!(matches!(
+            InstrumentIndex::load(&fpath),
+            Err(MdError::BadPositions(3))
+        ));
+        bad = built.clone();
+        bad.positions.get_mut(&2).unwrap().push(300);
+        bad.save(&fpath).unwrap();
+        assert!(matches!(
+            InstrumentIndex::load(&fpath),
+            Err(MdError::BadPositions(2))
+        ));
+        std::fs::write(&fpath, &b"TSII\x01\0\0\0"[..]).unwrap();
+        assert!(InstrumentIndex::load(&fpath).is_err());
+        _ = std::fs::remove_file(fpath);
+        _ = std::fs::remove_file(spath);
+    }
+}
@@ -0,0 +1,190 @@
+//! lz - LZ77 block compression and CRC32 of archive blocks
+//!
+//! Sequences are LZ4 alike: a token of literal length (high nibble) and
+//! match length - 4 (low nibble), 15 extended by bytes of 255 runs, the
+//! literals, then u16 offset of the match. The last sequence has
+//! literals only.
+
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 12;
+const MAX_OFFSET: usize = 65535;
+
+fn read_u32(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+fn hash(v: u32) -> usize {
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn put_len(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn emit(out: &mut Vec<u8>, lit: &[u8], mat: Option<(usize, usize)>) {
+    let ll = lit.len();
+    let ml = mat.map(|(_, l)| l - MIN_MATCH).unwrap_or(0);
+    out.push(((ll.min(15) as u8) << 4) | ml.min(15) as u8);
+    if ll >= 15 {
+        put_len(out, ll - 15);
+    }
+    out.extend_from_slice(lit);
+    if let Some((off, _)) = mat {
+        out.extend_from_slice(&(off as u16).to_le_bytes());
+        if ml >= 15 {
+            put_len(out, ml - 15);
+        }
+    }
+}
+
+/// Append compressed src to out
+pub fn compress(src: &[u8], out: &mut Vec<u8>) {
+    // position + 1 of last 4 bytes of same hash, 0 for none
+    let mut table = vec![0u32; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= src.len() {
+        let v = read_u32(src, i);
+        let h = hash(v);
+        let cand = table[h] as usize;
+        table[h] = i as u32 + 1;
+        if cand > 0 && i - (cand - 1) <= MAX_OFFSET && read_u32(src, cand - 1) == v {
+            let c = cand - 1;
+            let mut ml = MIN_MATCH;
+            while i + ml < src.len() && src[c + ml] == src[i + ml] {
+                ml += 1;
+            }
+            emit(out, &src[anchor..i], Some((i - c, ml)));
+            i += ml;
+            anchor = i;
+            continue;
+        }
+        i += 1;
+    }
+    emit(out, &src[anchor..], None);
+}
+
+fn get_len(src: &[u8], p: &mut usize) -> Option<usize> {
+    let mut n = 0;
+    loop {
+        let b = *src.get(*p)?;
+        *p += 1;
+        n += b as usize;
+        if b != 255 {
+            return Some(n);
+        }
+    }
+}
+
+/// Decompress src of raw_len bytes, None if corrupted
+pub fn decompress(src: &[u8], raw_len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(raw_len);
+    let mut p = 0;
+    while p < src.len() {
+        let token = src[p];
+        p += 1;
+        let mut ll = (token >> 4) as usize;
+        if ll == 15 {
+            ll += get_len(src, &mut p)?;
+        }
+        if p + ll > src.len() || out.len() + ll > raw_len {
+            return None;
+        }
+        out.extend_from_slice(&src[p..p + ll]);
+        p += ll;
+        if p == src.len() {
+            break;
+        }
+        if p + 2 > src.len() {
+            return None;
+        }
+        let off = u16::from_le_bytes([src[p], src[p + 1]]) as usize;
+        p += 2;
+        let mut ml = (token & 15) as usize;
+        if ml == 15 {
+            ml += get_len(src, &mut p)?;
+        }
+        ml += MIN_MATCH;
+        if off == 0 || off > out.len() || out.len() + ml > raw_len {
+            return None;
+        }
+        // match may overlap bytes it produces
+        let start = out.len() - off;
+        for k in 0..ml {
+            let b = out[start + k];
+            out.push(b);
+        }
+    }
+    if out.len() != raw_len {
+        return None;
+    }
+    Some(out)
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 (IEEE) of buf
+pub fn crc32(buf: &[u8]) -> u32 {
+    let mut c = !0u32;
+    for b in buf {
+        c = CRC_TABLE[((c ^ *b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_lz() {
+        let mut src = Vec::new();
+        for i in 0..5000u32 {
+            src.extend_from_slice(b"AddOrder ");
+            src.extend_from_slice(&(i % 37).to_le_bytes());
+        }
+        src.extend_from_slice(&[7u8; 1000]);
+        let mut out = Vec::new();
+        compress(&src, &mut out);
+        println!("lz {} -> {}", src.len(), out.len());
+        assert!(out.len() < src.len() / 4);
+        assert_eq!(decompress(&out, src.len()).unwrap(), src);
+        for ss in [&b""[..], b"abc", b"abcdabcdabcdabcdabcdabcd"] {
+            let mut out = Vec::new();
+            compress(ss, &mut out);
+            assert_eq!(decompress(&out, ss.len()).unwrap(), ss);
+        }
+        assert!(decompress(&out, src.len() + 1).is_none());
+        assert!(decompress(&out[..out.len() / 2], src.len()).is_none());
+    }
+}
@@ -0,0 +1,827 @@
+//! mdcache - shared memory series of ClMessage records
+//!
+//! A series file is a 64 bytes MdHeader followed by `max_messages`
+//! records, written by one MdCacheWriter and read by any number of
+//! MdCache readers.
+
+mod archive;
+mod error;
+mod iidx;
+mod lz;
+mod tidx;
+
+pub use archive::{Archive, ARCHIVE_BLOCK_MSGS};
+pub use error::{MdError, Result};
+pub use iidx::{iidx_path, InstrumentIndex, Select};
+pub use tidx::{tidx_path, TimeEntry, TimeIndex, TIDX_STRIDE};
+
+use crate::datetime::DateTimeSec;
+use crate::mmap::{hp_path, hugepage_size, hugetlbfs_mounts};
+use crate::pitch::MarketCategory;
+use crate::{nsleep, ClMessage, DateTime, Mmap, TimeVal};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::thread;
+
+const MDSERIES_PATH: &'static str = "mdseries.bin";
+/// magic of series written by this crate, "TSMD"
+pub const MD_MAGIC: u32 = u32::from_le_bytes(*b"TSMD");
+pub const MD_VERSION: u32 = 1;
+const MD_HEADER_LEN: u64 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct MdHeader {
+    pub init_time: i64,
+    pub shut_time: i64,
+    pub max_messages: u64,
+    pub cnt_messages: u64,
+    pub rec_size: i32,
+    pub session_no: i32,
+    pub md_len: u64,
+    /// 0 of series written by legacy writer
+    pub magic: u32,
+    pub version: u32,
+    pub reserved: u64,
+}
+
+impl fmt::Display for MdHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let init_t: DateTimeSec = DateTime::new(self.init_time);
+        let shut_t: DateTimeSec = DateTime::new(self.shut_time);
+        write!(
+            f,
+            "init_time: {}, shut_time: {}\nsession: {} max_msg: {}, cnt_msgs: {}",
+            init_t, shut_t, self.session_no, self.max_messages, self.cnt_messages
+        )
+    }
+}
+
+// series file on hugetlbfs if any, else on /dev/shm
+fn series_path(name: &str) -> String {
+    if let Ok(fp) = hp_path(name) {
+        fp
+    } else {
+        "/dev/shm/".to_owned() + name
+    }
+}
+
+/// Series name of market and trading session, e.g. `mdseries_shfe_1.bin`
+pub fn series_name(market: MarketCategory, session_no: i32) -> String {
+    let mkt = match market {
+        MarketCategory::Shfe => "shfe",
+        MarketCategory::Dce => "dce",
+        MarketCategory::Czce => "czce",
+        MarketCategory::Cffex => "cffex",
+        MarketCategory::Gce => "gce",
+        MarketCategory::Sse => "sse",
+        MarketCategory::Szse => "szse",
+        MarketCategory::Unavailable => "any",
+    };
+    format!("mdseries_{}_{}.bin", mkt, session_no)
+}
+
+/// Read access shared by MdCache and Archive
+pub trait Series {
+    fn header(&self) -> &MdHeader;
+    /// Messages published so far
+    fn published(&self) -> &[ClMessage];
+    /// File path of series
+    fn path(&self) -> &str;
+    fn len(&self) -> usize {
+        self.published().len()
+    }
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Series found by `list_series`
+#[derive(Clone)]
+pub struct SeriesInfo {
+    pub name: String,
+    pub path: String,
+    pub header: MdHeader,
+}
+
+impl fmt::Display for SeriesInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})\n{}", self.name, self.path, self.header)
+    }
+}
+
+/// Valid series files on /dev/shm and hugetlbfs mounts
+pub fn list_series() -> Vec<SeriesInfo> {
+    let mut dirs = vec!["/dev/shm".to_owned()];
+    dirs.extend(hugetlbfs_mounts());
+    let mut res = Vec::new();
+    for dir in dirs {
+        let rd = match std::fs::read_dir(&dir) {
+            Ok(rd) => rd,
+            Err(_) => continue,
+        };
+        for ent in rd.flatten() {
+            if !ent.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                continue;
+            }
+            let path = ent.path().to_string_lossy().into_owned();
+            if let Ok((header, _)) = MdHeader::load_path(&path) {
+                let name = ent.file_name().to_string_lossy().into_owned();
+                res.push(SeriesInfo { name, path, header });
+            }
+        }
+    }
+    res
+}
+
+// cnt_messages is published by writer with release ordering
+fn cnt_messages(md: &MdHeader) -> &AtomicU64 {
+    let p = &md.cnt_messages as *const u64 as *const AtomicU64;
+    unsafe { &*p }
+}
+
+// shut_time is set by writer on close with release ordering
+fn shut_time(md: &MdHeader) -> &AtomicI64 {
+    let p = &md.shut_time as *const i64 as *const AtomicI64;
+    unsafe { &*p }
+}
+
+impl MdHeader {
+    pub fn new() -> Result<MdHeader> {
+        MdHeader::load(MDSERIES_PATH)
+    }
+    /// Read and validate header of series `name`
+    pub fn load(name: &str) -> Result<MdHeader> {
+        let (md, _) = MdHeader::load_path(&series_path(name))?;
+        Ok(md)
+    }
+    /// Read and validate header of series file fpath, with file length
+    pub fn load_path(fpath: &str) -> Result<(MdHeader, u64)> {
+        let mut fd = File::open(fpath)?;
+        let file_len = fd.metadata()?.len();
+        if file_len < MD_HEADER_LEN {
+            let md_len = MD_HEADER_LEN;
+            return Err(MdError::Truncated { md_len, file_len });
+        }
+        let mut buf = [0u8; MD_HEADER_LEN as usize];
+        fd.read_exact(&mut buf)?;
+        let md = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const MdHeader) };
+        md.validate(file_len)?;
+        Ok((md, file_len))
+    }
+    /// Check magic, version and layout against series file length
+    pub fn validate(&self, file_len: u64) -> Result<()> {
+        if self.magic != MD_MAGIC && self.magic != 0 {
+            return Err(MdError::BadMagic(self.magic));
+        }
+        if self.magic == MD_MAGIC && self.version != MD_VERSION {
+            return Err(MdError::UnsupportedVersion(self.version));
+        }
+        if self.rec_size as usize != std::mem::size_of::<ClMessage>() {
+            return Err(MdError::RecSize(self.rec_size));
+        }
+        let need = self
+            .max_messages
+            .checked_mul(self.rec_size as u64)
+            .and_then(|v| v.checked_add(MD_HEADER_LEN));
+        match need {
+            Some(need) if need <= self.md_len => (),
+            _ => {
+                return Err(MdError::Layout {
+                    max_messages: self.max_messages,
+                    md_len: self.md_len,
+                })
+            }
+        }
+        if file_len < self.md_len {
+            let md_len = self.md_len;
+            return Err(MdError::Truncated { md_len, file_len });
+        }
+        if self.cnt_messages > self.max_messages {
+            return Err(MdError::Count {
+                cnt_messages: self.cnt_messages,
+                max_messages: self.max_messages,
+            });
+        }
+        Ok(())
+    }
+}
+
+pub struct MdCache<'a> {
+    mmap: Mmap,
+    md_header: &'a MdHeader,
+    msgs: &'a [ClMessage],
+}
+
+impl<'a> MdCache<'a> {
+    pub fn new() -> Result<MdCache<'a>> {
+        MdCache::open(MDSERIES_PATH)
+    }
+    /// Map series `name` read only
+    pub fn open(name: &str) -> Result<MdCache<'a>> {
+        MdCache::open_path(&series_path(name))
+    }
+    /// Map series file fpath read only
+    pub fn open_path(fpath: &str) -> Result<MdCache<'a>> {
+        let (md, _) = MdHeader::load_path(fpath)?;
+        let mut mmap = Mmap::with_path(fpath, md.md_len, true);
+        if !mmap.open() {
+            return Err(MdError::MapFailed(fpath.to_owned()));
+        }
+        let md_p = mmap.ptr() as *const MdHeader;
+        let md_header = unsafe { &(*md_p) };
+        let msg_p = unsafe { mmap.ptr().add(64) as *const ClMessage };
+        let nmsg = md.max_messages as usize;
+        let msgs = unsafe { &(*std::ptr::slice_from_raw_parts(msg_p, nmsg)) };
+        Ok(MdCache {
+            mmap,
+            md_header,
+            msgs,
+        })
+    }
+    pub fn header(&self) -> &MdHeader {
+        self.md_header
+    }
+    /// File path of series
+    pub fn path(&self) -> &str {
+        self.mmap.path()
+    }
+    pub fn msgs(&self) -> &[ClMessage] {
+        self.msgs
+    }
+    /// Messages published so far, acquire load of `cnt_messages`
+    pub fn len(&self) -> usize {
+        cnt_messages(self.md_header).load(Ordering::Acquire) as usize
+    }
+    pub fn cap(&self) -> usize {
+        self.md_header.max_messages as usize
+    }
+    /// Writer closed the series, no more messages to come
+    pub fn is_shut(&self) -> bool {
+        shut_time(self.md_header).load(Ordering::Acquire) != 0
+    }
+    /// Messages published so far
+    pub fn published(&self) -> &[ClMessage] {
+        &self.msgs[..self.len().min(self.msgs.len())]
+    }
+    /// Tail messages published from now on
+    pub fn tail(&self, backoff: BackOff) -> MdTail<'_, 'a> {
+        self.tail_from(self.len(), backoff)
+    }
+    /// Tail messages from pos, e.g. `MdTail::position` saved before restart
+    pub fn tail_from(&self, pos: usize, backoff: BackOff) -> MdTail<'_, 'a> {
+        MdTail {
+            md: self,
+            pos,
+            backoff,
+        }
+    }
+}
+
+impl Series for MdCache<'_> {
+    fn header(&self) -> &MdHeader {
+        self.md_header
+    }
+    fn published(&self) -> &[ClMessage] {
+        MdCache::published(self)
+    }
+    fn path(&self) -> &str {
+        self.mmap.path()
+    }
+}
+
+/// Wait policy of MdTail while no message published
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackOff {
+    Spin,
+    Yield,
+    /// nsleep nanoseconds
+    Sleep(u64),
+}
+
+impl BackOff {
+    fn wait(&self) {
+        match *self {
+            BackOff::Spin => std::hint::spin_loop(),
+            BackOff::Yield => thread::yield_now(),
+            BackOff::Sleep(ns) => nsleep(ns),
+        }
+    }
+}
+
+/// Cursor over messages of MdCache as writer publishes them, iteration
+/// blocks with back off and ends once series shut and drained
+pub struct MdTail<'c, 'a> {
+    md: &'c MdCache<'a>,
+    pos: usize,
+    backoff: BackOff,
+}
+
+impl<'c, 'a> MdTail<'c, 'a> {
+    /// Position of the next message, resume point
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+    pub fn set_backoff(&mut self, backoff: BackOff) {
+        self.backoff = backoff;
+    }
+    /// Messages published not read yet
+    pub fn available(&self) -> usize {
+        self.md.published().len().saturating_sub(self.pos)
+    }
+    /// Next message if published, never waits
+    pub fn try_next(&mut self) -> Option<&'c ClMessage> {
+        let msgs = self.md.published();
+        if self.pos >= msgs.len() {
+            return None;
+        }
+        self.pos += 1;
+        Some(&msgs[self.pos - 1])
+    }
+    /// All messages published not read yet, never waits
+    pub fn batch(&mut self) -> &'c [ClMessage] {
+        let msgs = self.md.published();
+        if self.pos >= msgs.len() {
+            return &[];
+        }
+        let res = &msgs[self.pos..];
+        self.pos = msgs.len();
+        res
+    }
+}
+
+impl<'c, 'a> Iterator for MdTail<'c, 'a> {
+    type Item = &'c ClMessage;
+    fn next(&mut self) -> Option<&'c ClMessage> {
+        loop {
+            if let Some(msg) = self.try_next() {
+                return Some(msg);
+            }
+            // check count again, writer may publish before close
+            if self.md.is_shut() && self.available() == 0 {
+                return None;
+            }
+            self.backoff.wait();
+        }
+    }
+}
+
+/// Appends messages to an existing series, only one writer per series
+pub struct MdCacheWriter {
+    mmap: Mmap,
+    cap: usize,
+    iidx: Option<InstrumentIndex>,
+}
+
+// mapping is owned by writer, header count is published atomically
+unsafe impl Send for MdCacheWriter {}
+
+impl MdCacheWriter {
+    pub fn new() -> Result<MdCacheWriter> {
+        MdCacheWriter::open(MDSERIES_PATH)
+    }
+    /// Map series `name` read write, append after messages already there
+    pub fn open(name: &str) -> Result<MdCacheWriter> {
+        MdCacheWriter::open_path(&series_path(name))
+    }
+    /// Map series file fpath read write
+    pub fn open_path(fpath: &str) -> Result<MdCacheWriter> {
+        let (md, _) = MdHeader::load_path(fpath)?;
+        let mut mmap = Mmap::with_path(fpath, md.md_len, false);
+        if !mmap.open() {
+            return Err(MdError::MapFailed(fpath.to_owned()));
+        }
+        let wr = MdCacheWriter {
+            mmap,
+            cap: md.max_messages as usize,
+            iidx: None,
+        };
+        // reopen of closed series, tail readers follow new messages
+        shut_time(wr.header()).store(0, Ordering::Release);
+        Ok(wr)
+    }
+    /// Create series `name` of max_messages records, on hugetlbfs if
+    /// mounted and hugepages available else on /dev/shm, fail if series
+    /// exists
+    pub fn create(name: &str, max_messages: u64, session_no: i32) -> Result<MdCacheWriter> {
+        if let Some(mnt) = hugetlbfs_mounts().into_iter().next() {
+            let fpath = mnt + "/" + name;
+            match MdCacheWriter::create_path(&fpath, max_messages, session_no) {
+                Ok(wr) => return Ok(wr),
+                Err(MdError::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(MdError::Io(e))
+                }
+                Err(_) => _ = std::fs::remove_file(&fpath),
+            }
+        }
+        let fpath = "/dev/shm/".to_owned() + name;
+        MdCacheWriter::create_path(&fpath, max_messages, session_no)
+    }
+    /// Create series file fpath, length rounded up to huge page size if
+    /// on hugetlbfs, fail if file exists
+    pub fn create_path(fpath: &str, max_messages: u64, session_no: i32) -> Result<MdCacheWriter> {
+        let layout = MdError::Layout {
+            max_messages,
+            md_len: 0,
+        };
+        let mut md_len = max_messages
+            .checked_mul(std::mem::size_of::<ClMessage>() as u64)
+            .and_then(|v| v.checked_add(MD_HEADER_LEN))
+            .ok_or(layout)?;
+        if hugetlbfs_mounts()
+            .iter()
+            .any(|mnt| fpath.starts_with(mnt.as_str()))
+        {
+            let hp_size = hugepage_size();
+            md_len = md_len
+                .div_ceil(hp_size)
+                .checked_mul(hp_size)
+                .ok_or(MdError::Layout {
+                    max_messages,
+                    md_len,
+                })?;
+        }
+        if std::path::Path::new(fpath).exists() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+        }
+        let mut mmap = Mmap::with_path(fpath, md_len, false);
+        if !mmap.create() {
+            return Err(MdError::MapFailed(fpath.to_owned()));
+        }
+        let md = MdHeader {
+            init_time: TimeVal::now().as_secs() as i64,
+            shut_time: 0,
+            max_messages,
+            cnt_messages: 0,
+            rec_size: std::mem::size_of::<ClMessage>() as i32,
+            session_no,
+            md_len: mmap.len() as u64,
+            magic: MD_MAGIC,
+            version: MD_VERSION,
+            reserved: 0,
+        };
+        unsafe { std::ptr::write(mmap.mut_ptr() as *mut MdHeader, md) };
+        Ok(MdCacheWriter {
+            mmap,
+            cap: max_messages as usize,
+            iidx: None,
+        })
+    }
+    /// Remove series `name` and its index files, readers still mapping
+    /// the series keep the old file
+    pub fn remove(name: &str) -> Result<()> {
+        MdCacheWriter::remove_path(&series_path(name))
+    }
+    /// Remove series file fpath and its index files
+    pub fn remove_path(fpath: &str) -> Result<()> {
+        std::fs::remove_file(fpath)?;
+        for sidecar in [tidx_path(fpath), iidx_path(fpath)] {
+            match std::fs::remove_file(sidecar) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+    /// Record shut_time, readers take series as complete, save
+    /// instrument index if enabled
+    pub fn close(self) -> Result<()> {
+        let now = TimeVal::now().as_secs() as i64;
+        shut_time(self.header()).store(now, Ordering::Release);
+        if let Some(iidx) = &self.iidx {
+            iidx.save(&iidx_path(self.path()))?;
+        }
+        Ok(())
+    }
+    /// Keep instrument index of messages written, saved on close
+    pub fn enable_instrument_index(&mut self) {
+        if self.iidx.is_none() {
+            let msgs = unsafe {
+                let msg_p = self.mmap.ptr().add(64) as *const ClMessage;
+                std::slice::from_raw_parts(msg_p, self.len())
+            };
+            self.iidx = Some(InstrumentIndex::build(msgs).of_series(self.header()));
+        }
+    }
+    pub fn instrument_index(&self) -> Option<&InstrumentIndex> {
+        self.iidx.as_ref()
+    }
+    /// File path of series
+    pub fn path(&self) -> &str {
+        self.mmap.path()
+    }
+    pub fn header(&self) -> &MdHeader {
+        unsafe { &*(self.mmap.ptr() as *const MdHeader) }
+    }
+    pub fn len(&self) -> usize {
+        cnt_messages(self.header()).load(Ordering::Relaxed) as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.cap
+    }
+    /// Store msg then publish it, return its position in series
+    pub fn push(&mut self, msg: &ClMessage) -> Result<usize> {
+        let pos = self.len();
+        if pos >= self.cap {
+            return Err(MdError::Full);
+        }
+        unsafe {
+            let msg_p = self.mmap.mut_ptr().add(64) as *mut ClMessage;
+            std::ptr::write(msg_p.add(pos), *msg);
+        }
+        cnt_messages(self.header()).store(pos as u64 + 1, Ordering::Release);
+        if let Some(iidx) = &mut self.iidx {
+            iidx.push(msg.data());
+        }
+        Ok(pos)
+    }
+    /// Append raw message bytes, at most 62 bytes
+    pub fn append(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() > ClMessage::default().cap() {
+            return Err(MdError::TooLong(buf.len()));
+        }
+        self.push(&ClMessage::new(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::Measure;
+    use crate::testutil::test_series_name;
+
+    #[test]
+    fn test_hp_path() {
+        let ss = "mdseries.bin";
+        if let Ok(fpath) = hp_path(ss) {
+            println!("got {} shared memory path: {}", ss, fpath);
+        } else {
+            println!("no {} on shared memory", ss);
+        }
+    }
+
+    #[test]
+    fn test_mmap() {
+        if let Ok(md) = MdHeader::new() {
+            println!(
+                "MdHeader: rec_size({}) cnt({}) len({})",
+                md.rec_size, md.cnt_messages, md.md_len
+            );
+            let mut map = Mmap::new("mdseries.bin", md.md_len, true, true);
+            map.open();
+            assert!(!map.is_null());
+            let md = unsafe { &(*(map.mut_ptr() as *const MdHeader)) };
+            println!("MdHeader: {}", md);
+        } else {
+            assert!(false, "MdHeader::new()");
+        }
+    }
+
+    #[test]
+    fn test_mdcache_writer() {
+        let name = &test_series_name("mdcache_test");
+        let mut wr = MdCacheWriter::create(name, 4, 3).unwrap();
+        let fpath = wr.path().to_owned();
+        println!("create series on {}", fpath);
+        assert!(wr.is_empty());
+        assert_eq!(wr.append(b"abc").unwrap(), 0);
+        assert_eq!(wr.push(&ClMessage::new(b"def")).unwrap(), 1);
+        assert!(wr.append(&[0u8; 63]).is_err());
+        wr.close().unwrap();
+        let md = MdHeader::load(name).unwrap();
+        println!("MdHeader: {}", md);
+        assert_eq!(md.rec_size, 64);
+        assert_eq!(md.session_no, 3);
+        assert_eq!(md.max_messages, 4);
+        assert_eq!(md.cnt_messages, 2);
+        assert!(md.md_len >= 64 + 4 * 64);
+        assert!(md.init_time != 0 && md.shut_time >= md.init_time);
+        assert!(matches!(
+            MdCacheWriter::create(name, 4, 3),
+            Err(MdError::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists
+        ));
+        assert!(matches!(
+            MdCacheWriter::create_path("/dev/shm/md_huge.bin", u64::MAX, 1),
+            Err(MdError::Layout { .. })
+        ));
+        let mut wr = MdCacheWriter::open(name).unwrap();
+        assert!(!MdCache::open(name).unwrap().is_shut());
+        wr.append(b"g").unwrap();
+        wr.append(b"h").unwrap();
+        assert!(wr.is_full());
+        assert!(wr.append(b"i").is_err());
+        let md = MdCache::open(name).unwrap();
+        assert_eq!(md.len(), 4);
+        assert_eq!(md.msgs()[1].data(), b"def");
+        assert_eq!(md.msgs()[3].data(), b"h");
+        std::fs::write(tidx_path(&fpath), b"stale").unwrap();
+        MdCacheWriter::remove(name).unwrap();
+        assert!(!std::path::Path::new(&tidx_path(&fpath)).exists());
+        // reader keeps mapping of removed series
+        assert_eq!(md.msgs()[3].data(), b"h");
+        let wr = MdCacheWriter::create(name, 4, 3).unwrap();
+        assert!(wr.is_empty());
+        MdCacheWriter::remove_path(&fpath).unwrap();
+    }
+
+    #[test]
+    fn test_list_series() {
+        assert_eq!(series_name(MarketCategory::Shfe, 1), "mdseries_shfe_1.bin");
+        assert_eq!(series_name(MarketCategory::Dce, 2), "mdseries_dce_2.bin");
+        let pid = std::process::id() as i32;
+        let (shfe, dce) = (
+            series_name(MarketCategory::Shfe, pid),
+            series_name(MarketCategory::Dce, pid),
+        );
+        let garbage = "/dev/shm/".to_owned() + &test_series_name("not_series");
+        let w1 = MdCacheWriter::create(&shfe, 4, 1).unwrap();
+        let w2 = MdCacheWriter::create_path(&("/dev/shm/".to_owned() + &dce), 8, 2).unwrap();
+        std::fs::write(&garbage, b"garbage").unwrap();
+        let all = list_series();
+        for ss in all.iter() {
+            println!("series: {}", ss);
+        }
+        let ss = all.iter().find(|s| s.name == shfe).unwrap();
+        assert_eq!(ss.path, w1.path());
+        assert_eq!(ss.header.session_no, 1);
+        let ss = all.iter().find(|s| s.name == dce).unwrap();
+        assert_eq!(ss.header.max_messages, 8);
+        assert!(!all.iter().any(|s| garbage.ends_with(&s.name)));
+        let md = MdCache::open_path(w2.path()).unwrap();
+        assert_eq!(md.cap(), 8);
+        for fp in [w1.path(), w2.path(), &garbage] {
+            _ = std::fs::remove_file(fp);
+        }
+    }
+
+    #[test]
+    fn test_md_validate() {
+        assert_eq!(std::mem::size_of::<MdHeader>(), 64);
+        let name = &test_series_name("mdvalid_test");
+        let fpath = "/dev/shm/".to_owned() + name;
+        let wr = MdCacheWriter::create(name, 4, 1).unwrap();
+        let md = *wr.header();
+        assert_eq!(md.magic, MD_MAGIC);
+        assert_eq!(md.version, MD_VERSION);
+        wr.close().unwrap();
+        let write_md = |md: &MdHeader, file_len: usize| {
+            let mut buf = vec![0u8; file_len];
+            let hdr = unsafe { std::slice::from_raw_parts(md as *const MdHeader as *const u8, 64) };
+            let ll = file_len.min(64);
+            buf[..ll].copy_from_slice(&hdr[..ll]);
+            std::fs::write(&fpath, buf).unwrap();
+        };
+        let md_len = md.md_len as usize;
+        // legacy writer leaves magic 0
+        write_md(
+            &MdHeader {
+                magic: 0,
+                version: 0,
+                ..md
+            },
+            md_len,
+        );
+        assert!(MdHeader::load(name).is_ok());
+        write_md(&MdHeader { magic: 1, ..md }, md_len);
+        assert!(matches!(MdHeader::load(name), Err(MdError::BadMagic(1))));
+        write_md(&MdHeader { version: 9, ..md }, md_len);
+        assert!(matches!(
+            MdHeader::load(name),
+            Err(MdError::UnsupportedVersion(9))
+        ));
+        write_md(&MdHeader { rec_size: 32, ..md }, md_len);
+        assert!(matches!(MdHeader::load(name), Err(MdError::RecSize(32))));
+        write_md(
+            &MdHeader {
+                max_messages: 1 << 60,
+                ..md
+            },
+            md_len,
+        );
+        assert!(matches!(MdHeader::load(name), Err(MdError::Layout { .. })));
+        write_md(&md, md_len - 1);
+        let err = MdCache::open(name).err().unwrap();
+        println!("open truncated: {}", err);
+        assert!(matches!(err, MdError::Truncated { .. }));
+        write_md(&md, 10);
+        assert!(matches!(
+            MdHeader::load(name),
+            Err(MdError::Truncated { .. })
+        ));
+        write_md(
+            &MdHeader {
+                cnt_messages: 5,
+                ..md
+            },
+            md_len,
+        );
+        assert!(matches!(
+            MdCacheWriter::open(name),
+            Err(MdError::Count { .. })
+        ));
+        assert!(matches!(MdHeader::load("no_such.bin"), Err(MdError::Io(_))));
+        _ = std::fs::remove_file(fpath);
+    }
+
+    #[test]
+    fn test_mdcache_tail() {
+        let name = &test_series_name("mdtail_test");
+        let mut wr = MdCacheWriter::create(name, 100, 1).unwrap();
+        let fpath = wr.path().to_owned();
+        wr.append(b"0").unwrap();
+        let md = MdCache::open(name).unwrap();
+        let mut tail = md.tail_from(0, BackOff::Sleep(100_000));
+        assert_eq!(tail.try_next().unwrap().data(), b"0");
+        assert!(tail.try_next().is_none());
+        let th = thread::spawn(move || {
+            for i in 1..50u8 {
+                wr.append(&[i]).unwrap();
+                if i % 10 == 0 {
+                    nsleep(1_000_000);
+                }
+            }
+            wr.close().unwrap();
+        });
+        let mut cnt = 1;
+        for msg in &mut tail {
+            assert_eq!(msg.data(), &[cnt as u8]);
+            cnt += 1;
+        }
+        th.join().unwrap();
+        assert_eq!(cnt, 50);
+        assert_eq!(tail.position(), 50);
+        // resume from saved position
+        let mut tail = md.tail_from(45, BackOff::Spin);
+        assert_eq!(tail.available(), 5);
+        assert_eq!(tail.batch().len(), 5);
+        assert!(tail.next().is_none());
+        assert_eq!(md.tail(BackOff::Yield).count(), 0);
+        _ = std::fs::remove_file(fpath);
+    }
+
+    #[test]
+    fn test_mdcache() {
+        if let Ok(md) = MdCache::new() {
+            println!("MdHeader: {}", md.md_header);
+            assert_eq!(md.msgs.len(), md.md_header.max_messages as usize);
+        }
+    }
+
+    #[test]
+    fn test_md_pitch() {
+        use crate::pitch::{from_bytes, Body, Message};
+        use crate::TimeVal;
+        if let Ok(md) = MdCache::new() {
+            let msgs = md.msgs;
+            for i in 0..10 {
+                println!("parse pitch: tag {:x}", msgs[i as usize].data()[0]);
+                let msg: Message = from_bytes(msgs[i as usize].data()).unwrap();
+                println!("No{}: {}", i, msg);
+                match &msg.body {
+                    Body::SymbolDirectory(s) => {
+                        println!("symbol: {}", s.symbol);
+                    }
+                    Body::SystemEvent(s) => {
+                        let dt = TimeVal::from_hours(s.time_hours);
+                        let dt = dt + (msg.timestamp as u64) * 1000;
+                        let dt = DateTimeSec::from(dt);
+                        println!("SystemEvent: {}, {}", s.event, dt);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_md_pitch() {
+        use crate::pitch::{from_bytes, Message};
+        let mut measure = Measure::start("mdCache pitch bench");
+        let md = MdCache::new().unwrap();
+        let msgs = md.msgs;
+        let cnt = md.len();
+        for i in 0..cnt {
+            let _msg: Message = from_bytes(msgs[i].data()).unwrap();
+        }
+        measure.stop();
+        let ns_ops = measure.as_ns() / (cnt as u64);
+        println!("mdCache pitch msgs({}) cost {} ns per Op", cnt, ns_ops);
+        #[cfg(feature = "tsc")]
+        {
+            let ns_ops = measure.as_ticks() / (cnt as u64);
+            println!("mdCache pitch cost {} ticks per Op", ns_ops);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! tidx - sparse time and tracking index of a series
+//!
+//! Every `stride` messages a (micros since epoch, position) entry is
+//! kept, and every `stride` messages of an instrument a (tracking,
+//! position) entry. Seeks binary search the entries then scan forward,
+//! a time seek at most `stride` records, a tracking seek at most `stride`
+//! messages of the instrument, more records if instruments interleave.
+//! The index is saved beside the series as `.tidx`.
+
+use super::{MdError, Result, Series};
+use crate::pitch::{peek_header, timeval_micros, MsgClock};
+use crate::{ClMessage, TimeVal};
+use std::collections::HashMap;
+
+const TIDX_MAGIC: u32 = u32::from_le_bytes(*b"TSTI");
+const TIDX_VERSION: u32 = 2;
+const TIDX_HEADER_LEN: usize = 48;
+pub const TIDX_STRIDE: usize = 1024;
+
+/// Position of first message at or after micros since epoch
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimeEntry {
+    pub micros: u64,
+    pub pos: u64,
+}
+
+// tracking unwrapped over u16 wraps of an instrument
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct TrackEntry {
+    seq: u64,
+    pos: u64,
+}
+
+#[derive(Default)]
+struct TrackState {
+    seq: u64,
+    cnt: usize,
+}
+
+/// Index file path of series file
+pub fn tidx_path(series_path: &str) -> String {
+    series_path.to_owned() + ".tidx"
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeIndex {
+    stride: usize,
+    count: u64,
+    hours: u32,
+    // identity of series indexed, from its MdHeader
+    init_time: i64,
+    session_no: i32,
+    times: Vec<TimeEntry>,
+    tracks: HashMap<u16, Vec<TrackEntry>>,
+}
+
+impl TimeIndex {
+    /// Index msgs, hours is hour base before first SystemEvent
+    pub fn build(msgs: &[ClMessage], hours: u32, stride: usize) -> TimeIndex {
+        let stride = stride.max(1);
+        let mut clk = MsgClock::new(hours);
+        let mut times = Vec::with_capacity(msgs.len() / stride + 1);
+        let mut tracks: HashMap<u16, Vec<TrackEntry>> = HashMap::new();
+        let mut states: HashMap<u16, TrackState> = HashMap::new();
+        for (pos, m) in msgs.iter().enumerate() {
+            let buf = m.data();
+            let micros = match clk.update_bytes(buf) {
+                Some(us) => us,
+                None => continue,
+            };
+            let pos = pos as u64;
+            if (pos as usize).is_multiple_of(stride) {
+                times.push(TimeEntry { micros, pos });
+            }
+            let (index, tracking, _) = peek_header(buf).unwrap_or_default();
+            let st = states.entry(index).or_default();
+            st.seq = unwrap_tracking(st.seq, st.cnt, tracking);
+            if st.cnt.is_multiple_of(stride) {
+                let seq = st.seq;
+                tracks
+                    .entry(index)
+                    .or_default()
+                    .push(TrackEntry { seq, pos });
+            }
+            st.cnt += 1;
+        }
+        TimeIndex {
+            stride,
+            count: msgs.len() as u64,
+            hours,
+            init_time: 0,
+            session_no: 0,
+            times,
+            tracks,
+        }
+    }
+    /// Load index saved beside series of md if it is of the same series
+    /// and stride and covers all messages published, else build and
+    /// save it
+    pub fn load_or_build<S: Series + ?Sized>(md: &S, stride: usize) -> Result<TimeIndex> {
+        let fpath = tidx_path(md.path());
+        let msgs = md.published();
+        let hdr = md.header();
+        if let Ok(tidx) = TimeIndex::load(&fpath) {
+            if tidx.count == msgs.len() as u64
+                && tidx.stride == stride.max(1)
+                && tidx.init_time == hdr.init_time
+                && tidx.session_no == hdr.session_no
+            {
+                return Ok(tidx);
+            }
+        }
+        let hours = TimeVal::new(hdr.init_time as u64, 0).as_hours();
+        let mut tidx = TimeIndex::build(msgs, hours, stride);
+        tidx.init_time = hdr.init_time;
+        tidx.session_no = hdr.session_no;
+        tidx.save(&fpath)?;
+        Ok(tidx)
+    }
+    /// Messages covered
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+    pub fn entries(&self) -> &[TimeEntry] {
+        &self.times
+    }
+    /// Position of first message at or after micros since epoch,
+    /// `msgs.len()` if none
+    pub fn seek_micros(&self, msgs: &[ClMessage], micros: u64) -> usize {
+        let idx = self.times.partition_point(|e| e.micros < micros);
+        let (mut clk, start) = if idx == 0 {
+            (MsgClock::new(self.hours), 0)
+        } else {
+            let e = &self.times[idx - 1];
+            (MsgClock::at(e.micros), e.pos as usize)
+        };
+        for (pos, m) in msgs.iter().enumerate().skip(start) {
+            if let Some(us) = clk.update_bytes(m.data()) {
+                if us >= micros {
+                    return pos;
+                }
+            }
+        }
+        msgs.len()
+    }
+    pub fn seek_time(&self, msgs: &[ClMessage], tv: &TimeVal) -> usize {
+        self.seek_micros(msgs, timeval_micros(tv))
+    }
+    /// Position of message of instrument index with tracking seq, seq
+    /// counts on past 65535 once tracking wraps
+    pub fn seek_tracking(&self, msgs: &[ClMessage], index: u16, seq: u64) -> Option<usize> {
+        let ents = self.tracks.get(&index)?;
+        let idx = ents.partition_point(|e| e.seq <= seq);
+        if idx == 0 {
+            return None;
+        }
+        let e = &ents[idx - 1];
+        let mut cur = e.seq;
+        for (pos, m) in msgs.iter().enumerate().skip(e.pos as usize) {
+            let (idx, tracking, _) = match peek_header(m.data()) {
+                Some(h) => h,
+                None => continue,
+            };
+            if idx != index {
+                continue;
+            }
+            if pos as u64 != e.pos {
+                cur = unwrap_tracking(cur, 1, tracking);
+            }
+            if cur == seq {
+                return Some(pos);
+            }
+            if cur > seq {
+                return None;
+            }
+        }
+        None
+    }
+    pub fn save(&self, fpath: &str) -> Result<()> {
+        let ntrk: usize = self.tracks.values().map(|v| v.len()).sum();
+        let mut buf = Vec::with_capacity(TIDX_HEADER_LEN + self.times.len() * 16 + ntrk * 24);
+        for v in [TIDX_MAGIC, TIDX_VERSION, self.stride as u32, self.hours] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [self.count, self.times.len() as u64] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        buf.extend_from_slice(&self.init_time.to_le_bytes());
+        buf.extend_from_slice(&self.session_no.to_le_bytes());
+        buf.extend_from_slice(&[0u8; 4]);
+        for e in &self.times {
+            buf.extend_from_slice(&e.micros.to_le_bytes());
+            buf.extend_from_slice(&e.pos.to_le_bytes());
+        }
+        let mut keys: Vec<&u16> = self.tracks.keys().collect();
+        keys.sort();
+        for k in keys {
+            for e in &self.tracks[k] {
+                buf.extend_from_slice(&(*k as u64).to_le_bytes());
+                buf.extend_from_slice(&e.seq.to_le_bytes());
+                buf.extend_from_slice(&e.pos.to_le_bytes());
+            }
+        }
+        std::fs::write(fpath, buf)?;
+        Ok(())
+    }
+    pub fn load(fpath: &str) -> Result<TimeIndex> {
+        let buf = std::fs::read(fpath)?;
+        let file_len = buf.len() as u64;
+        let truncated = MdError::Truncated {
+            md_len: TIDX_HEADER_LEN as u64,
+            file_len,
+        };
+        if buf.len() < TIDX_HEADER_LEN {
+            return Err(truncated);
+        }
+        let u32_at = |off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+        let u64_at = |off: usize| u64::from_le_bytes(buf[off..off + 8].try_into().unwrap());
+        if u32_at(0) != TIDX_MAGIC {
+            return Err(MdError::BadMagic(u32_at(0)));
+        }
+        if u32_at(4) != TIDX_VERSION {
+            return Err(MdError::UnsupportedVersion(u32_at(4)));
+        }
+        let (stride, hours, count, ntime) = (u32_at(8), u32_at(12), u64_at(16), u64_at(24));
+        let (init_time, session_no) = (u64_at(32) as i64, u32_at(40) as i32);
+        let trk_off = ntime
+            .checked_mul(16)
+            .and_then(|v| v.checked_add(TIDX_HEADER_LEN as u64))
+            .filter(|v| *v <= file_len && (file_len - *v).is_multiple_of(24));
+        let trk_off = match trk_off {
+            Some(off) => off as usize,
+            None => return Err(truncated),
+        };
+        let times = (TIDX_HEADER_LEN..trk_off)
+            .step_by(16)
+            .map(|off| TimeEntry {
+                micros: u64_at(off),
+                pos: u64_at(off + 8),
+            })
+            .collect();
+        let mut tracks: HashMap<u16, Vec<TrackEntry>> = HashMap::new();
+        for off in (trk_off..buf.len()).step_by(24) {
+            let e = TrackEntry {
+                seq: u64_at(off + 8),
+                pos: u64_at(off + 16),
+            };
+            tracks.entry(u64_at(off) as u16).or_default().push(e);
+        }
+        Ok(TimeIndex {
+            stride: stride as usize,
+            count,
+            hours,
+            init_time,
+            session_no,
+            times,
+            tracks,
+        })
+    }
+}
+
+// seq after tracking, cnt messages of instrument seen before
+fn unwrap_tracking(seq: u64, cnt: usize, tracking: u16) -> u64 {
+    if cnt == 0 {
+        tracking as u64
+    } else {
+        seq + tracking.wrapping_sub(seq as u16) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdcache::{MdCache, MdCacheWriter};
+    use crate::pitch::EventCode;
+    use crate::testutil::{add_orders, cl_message, system_event, test_series_name, HOURS};
+
+    fn series() -> Vec<ClMessage> {
+        let mut msgs = vec![cl_message(&system_event(EventCode::StartOfMessages, 0))];
+        // 2 instruments, 1 message per 10ms over 2 hours
+        msgs.extend(add_orders(0..720_000, |i| {
+            (1 + (i % 2) as u16, (i / 2) as u16, (i % 360_000) * 10_000)
+        }));
+        msgs
+    }
+
+    #[test]
+    fn test_time_index() {
+        let msgs = series();
+        let tidx = TimeIndex::build(&msgs, 0, 256);
+        assert_eq!(tidx.count(), msgs.len() as u64);
+        let base = HOURS as u64 * 3_600_000_000;
+        assert_eq!(tidx.seek_micros(&msgs, 0), 0);
+        // message i at base + i * 10ms, position i + 1
+        assert_eq!(tidx.seek_micros(&msgs, base + 10_000), 2);
+        assert_eq!(tidx.seek_micros(&msgs, base + 10_001), 3);
+        let pos = tidx.seek_micros(&msgs, base + 3_600_000_000 + 15_000_000);
+        assert_eq!(pos, 360_000 + 1_500 + 1);
+        let tv = TimeVal::from_hours(HOURS + 1) + 15_000_000_000;
+        assert_eq!(tidx.seek_time(&msgs, &tv), pos);
+        assert_eq!(tidx.seek_micros(&msgs, base + 7_200_000_000), msgs.len());
+        // instrument 2 tracking wraps at message 131072 of it
+        assert_eq!(tidx.seek_tracking(&msgs, 2, 5), Some(12));
+        assert_eq!(tidx.seek_tracking(&msgs, 1, 65536 + 7), Some(2 * 65543 + 1));
+        assert_eq!(tidx.seek_tracking(&msgs, 1, 400_000), None);
+        assert_eq!(tidx.seek_tracking(&msgs, 3, 1), None);
+    }
+
+    #[test]
+    fn test_time_index_file() {
+        let msgs = &series()[..200_000];
+        let name = &test_series_name("tidx_test");
+        let mut wr = MdCacheWriter::create(name, msgs.len() as u64, 1).unwrap();
+        for m in msgs[..100_000].iter() {
+            wr.push(m).unwrap();
+        }
+        let md = MdCache::open(name).unwrap();
+        let tidx = TimeIndex::load_or_build(&md, TIDX_STRIDE).unwrap();
+        let fpath = tidx_path(md.path());
+        assert_eq!(TimeIndex::load(&fpath).unwrap(), tidx);
+        // stale index is rebuilt
+        for m in msgs[100_000..].iter() {
+            wr.push(m).unwrap();
+        }
+        let tidx = TimeIndex::load_or_build(&md, TIDX_STRIDE).unwrap();
+        assert_eq!(tidx.count(), msgs.len() as u64);
+        assert_eq!(TimeIndex::load(&fpath).unwrap(), tidx);
+        // index of another series of same count is rebuilt
+        let other = TimeIndex {
+            init_time: tidx.init_time - 1,
+            times: Vec::new(),
+            ..tidx.clone()
+        };
+        other.save(&fpath).unwrap();
+        assert_eq!(TimeIndex::load_or_build(&md, TIDX_STRIDE).unwrap(), tidx);
+        // index of another stride is rebuilt
+        let tidx2 = TimeIndex::load_or_build(&md, 256).unwrap();
+        assert_eq!(tidx2.stride(), 256);
+        assert_eq!(TimeIndex::load(&fpath).unwrap(), tidx2);
+        assert_eq!(TimeIndex::load_or_build(&md, TIDX_STRIDE).unwrap(), tidx);
+        std::fs::write(&fpath, b"TSTI").unwrap();
+        assert!(TimeIndex::load(&fpath).is_err());
+        _ = std::fs::remove_file(fpath);
+        _ = std::fs::remove_file(wr.path());
+    }
+}
@@ -74,6 +74,38 @@ pub fn hp_path(fb: &str) -> Result<String> {
     Ok(res)
 }
 
+// hugetlbfs mount points
+#[cfg(target_os = "linux")]
+pub fn hugetlbfs_mounts() -> Vec<String> {
+    let ss = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    ss.lines()
+        .filter_map(|aline| {
+            let v: Vec<&str> = aline.split(' ').collect();
+            if v.len() >= 4 && v[2] == "hugetlbfs" {
+                Some(v[1].to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// default huge page size of system, 2MB if unknown
+#[cfg(target_os = "linux")]
+pub fn hugepage_size() -> u64 {
+    let ss = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    for aline in ss.lines() {
+        if let Some(v) = aline.strip_prefix("Hugepagesize:") {
+            if let Some(kb) = v.split_whitespace().next() {
+                if let Ok(kb) = kb.parse::<u64>() {
+                    return kb * 1024;
+                }
+            }
+        }
+    }
+    2 * 1024 * 1024
+}
+
 pub struct Mmap {
     base: *mut c_void,
     len: size_t,
@@ -117,8 +149,20 @@ impl Mmap {
             read_only,
         }
     }
+    /// Map file of explicit path, no hugetlbfs lookup
+    pub fn with_path(path: &str, len: u64, read_only: bool) -> Mmap {
+        Mmap {
+            base: std::ptr::null_mut(),
+            len: len as size_t,
+            flags: libc::MAP_SHARED,
+            path: path.to_owned(),
+            read_only,
+        }
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
     pub fn open(&mut self) -> bool {
-        let nullptr = 0 as *mut c_void;
         let fd = if self.read_only {
             if let Ok(ll) = file_len(&self.path) {
                 if ll == 0 {
@@ -134,12 +178,36 @@ impl Mmap {
             }
         } else {
             if let Ok(fd) = OpenOptions::new().read(true).write(true).open(&self.path) {
-                _ = fd.set_len(self.len as u64);
+                // grow only, never truncate series of other writer
+                if fd.metadata().map(|m| m.len()).unwrap_or(0) < self.len as u64 {
+                    _ = fd.set_len(self.len as u64);
+                }
                 fd
             } else {
                 return false;
             }
         };
+        self.map(&fd)
+    }
+    /// Create file of path, fail if it exists, then map it read write
+    pub fn create(&mut self) -> bool {
+        let fd = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&self.path)
+        {
+            Ok(fd) => fd,
+            Err(_) => return false,
+        };
+        if fd.set_len(self.len as u64).is_err() {
+            return false;
+        }
+        self.read_only = false;
+        self.map(&fd)
+    }
+    fn map(&mut self, fd: &File) -> bool {
+        let nullptr = 0 as *mut c_void;
         let prot = if self.read_only {
             libc::PROT_READ
         } else {
@@ -154,6 +222,9 @@ impl Mmap {
                 fd.as_raw_fd(),
                 0,
             );
+            if self.base == libc::MAP_FAILED {
+                self.base = nullptr;
+            }
             #[cfg(test)]
             if self.base.is_null() {
                 use std::ffi::CString;
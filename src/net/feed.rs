@@ -0,0 +1,319 @@
+//! feed - MoldUDP64 PITCH feed handler appending to a message sink
+//!
+//! `MdCacheWriter` as sink appends to a shared memory series, readers see
+//! each message once its `cnt_messages` is published.
+//!
+//! Packets are checked for session and sequence continuity, duplicate
+//! messages are dropped, messages failing PITCH decode are counted and
+//! not stored. Gaps are only counted, recovery is left to SoupBinTCP.
+
+use super::moldudp64::{MoldPacket, Session, MOLD_MTU};
+use crate::mdcache::MdCacheWriter;
+use crate::pitch::{from_bytes_mode, DecodeMode};
+use crate::ClMessage;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FeedStats {
+    pub packets: u64,
+    pub heartbeats: u64,
+    pub messages: u64,
+    /// malformed packets or of other session
+    pub invalid: u64,
+    /// messages failed to decode or store
+    pub rejected: u64,
+    pub duplicates: u64,
+    pub gaps: u64,
+    pub missing: u64,
+}
+
+impl fmt::Display for FeedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "packets: {}, messages: {}, invalid: {}, rejected: {}, dup: {}, gaps: {}, missing: {}",
+            self.packets,
+            self.messages,
+            self.invalid,
+            self.rejected,
+            self.duplicates,
+            self.gaps,
+            self.missing
+        )
+    }
+}
+
+/// Store of messages accepted by a feed
+pub trait FeedSink {
+    /// Append raw message, return its position in store
+    fn append(&mut self, buf: &[u8]) -> io::Result<usize>;
+}
+
+impl FeedSink for Vec<ClMessage> {
+    fn append(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > ClMessage::default().cap() {
+            return Err(io::Error::from(ErrorKind::InvalidInput));
+        }
+        self.push(ClMessage::new(buf));
+        Ok(self.len() - 1)
+    }
+}
+
+impl FeedSink for MdCacheWriter {
+    fn append(&mut self, buf: &[u8]) -> io::Result<usize> {
+        MdCacheWriter::append(self, buf).map_err(io::Error::other)
+    }
+}
+
+pub struct FeedHandler<S: FeedSink> {
+    sock: UdpSocket,
+    writer: S,
+    session: Option<Session>,
+    next_seq: u64,
+    mode: DecodeMode,
+    stats: FeedStats,
+    ended: bool,
+    buf: Vec<u8>,
+}
+
+impl<S: FeedSink> FeedHandler<S> {
+    pub fn new(sock: UdpSocket, writer: S) -> FeedHandler<S> {
+        FeedHandler {
+            sock,
+            writer,
+            session: None,
+            next_seq: 0,
+            mode: DecodeMode::Strict,
+            stats: Default::default(),
+            ended: false,
+            buf: vec![0; MOLD_MTU],
+        }
+    }
+    /// Unicast feed on addr
+    pub fn bind(addr: SocketAddr, writer: S) -> io::Result<FeedHandler<S>> {
+        let sock = UdpSocket::bind(addr)?;
+        Ok(FeedHandler::new(sock, writer))
+    }
+    /// Multicast feed of group:port, joined on interface iface
+    pub fn join_multicast(
+        group: Ipv4Addr,
+        port: u16,
+        iface: Ipv4Addr,
+        writer: S,
+    ) -> io::Result<FeedHandler<S>> {
+        let sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))?;
+        sock.join_multicast_v4(&group, &iface)?;
+        Ok(FeedHandler::new(sock, writer))
+    }
+    pub fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+    pub fn socket(&self) -> &UdpSocket {
+        &self.sock
+    }
+    pub fn writer(&self) -> &S {
+        &self.writer
+    }
+    pub fn stats(&self) -> &FeedStats {
+        &self.stats
+    }
+    /// Sequence number expected of the next message, 0 before first packet
+    pub fn next_sequence(&self) -> u64 {
+        self.next_seq
+    }
+    /// End of session packet received
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+    /// Receive one packet, return messages appended
+    pub fn poll(&mut self) -> io::Result<usize> {
+        let mut buf = std::mem::take(&mut self.buf);
+        let res = match self.sock.recv(&mut buf) {
+            Ok(ll) => Ok(self.handle_packet(&buf[..ll])),
+            Err(e) => Err(e),
+        };
+        self.buf = buf;
+        res
+    }
+    /// Receive until end of session, read timeout of socket ends it too
+    pub fn run(&mut self) -> io::Result<&FeedStats> {
+        while !self.ended {
+            match self.poll() {
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(&self.stats)
+    }
+    /// Validate packet and append its new messages, return messages appended
+    pub fn handle_packet(&mut self, buf: &[u8]) -> usize {
+        let pkt = match MoldPacket::parse(buf) {
+            Ok(pkt) => pkt,
+            Err(_) => {
+                self.stats.invalid += 1;
+                return 0;
+            }
+        };
+        match &self.session {
+            Some(ss) if ss[..] != *pkt.session() => {
+                self.stats.invalid += 1;
+                return 0;
+            }
+            Some(_) => (),
+            None => {
+                let mut ss: Session = Default::default();
+                ss.copy_from_slice(pkt.session());
+                self.session = Some(ss);
+                self.next_seq = pkt.sequence();
+            }
+        }
+        self.stats.packets += 1;
+        if pkt.is_end_of_session() {
+            self.ended = true;
+            return 0;
+        }
+        if pkt.is_heartbeat() {
+            self.stats.heartbeats += 1;
+            return 0;
+        }
+        let seq = pkt.sequence();
+        if seq > self.next_seq {
+            self.stats.gaps += 1;
+            self.stats.missing += seq - self.next_seq;
+            self.next_seq = seq;
+        }
+        let mut cnt = 0;
+        for (i, m) in pkt.messages().enumerate() {
+            if seq + (i as u64) < self.next_seq {
+                self.stats.duplicates += 1;
+                continue;
+            }
+            self.next_seq += 1;
+            if from_bytes_mode(m, self.mode).is_err() || self.writer.append(m).is_err() {
+                self.stats.rejected += 1;
+                continue;
+            }
+            self.stats.messages += 1;
+            cnt += 1;
+        }
+        cnt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdcache::MdCache;
+    use crate::net::{send_packet, PacketBuilder};
+    use crate::pitch::{from_bytes, to_bytes, AddOrder, Body, Message, Side, SymbolDirectory};
+    use crate::testutil::test_series_name;
+    use std::time::Duration;
+
+    fn add_order(tracking: u16) -> Message {
+        Message {
+            index: 1,
+            tracking,
+            timestamp: 1000,
+            body: Body::AddOrder(AddOrder {
+                reference: tracking as u64,
+                side: Side::Buy,
+                qty: 1,
+                price: 3000,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_feed_handler() {
+        let mut fh = FeedHandler::bind("127.0.0.1:0".parse().unwrap(), Vec::new()).unwrap();
+        fh.socket()
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let addr = fh.socket().local_addr().unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut pb = PacketBuilder::new("SHFE01", 1, MOLD_MTU);
+        for i in 1..=3 {
+            pb.push_message(&add_order(i)).unwrap();
+        }
+        let dup = pb.packet().to_vec();
+        send_packet(&tx, &addr, &mut pb).unwrap();
+        tx.send_to(&dup, addr).unwrap();
+        tx.send_to(&pb.heartbeat(), addr).unwrap();
+        // seq 4 lost
+        pb.push_message(&add_order(4)).unwrap();
+        pb.clear();
+        pb.push_message(&add_order(5)).unwrap();
+        pb.push(b"Zbad");
+        send_packet(&tx, &addr, &mut pb).unwrap();
+        tx.send_to(&pb.end_of_session(), addr).unwrap();
+        let st = *fh.run().unwrap();
+        println!("feed stats: {}", st);
+        assert!(fh.is_ended());
+        assert_eq!(fh.next_sequence(), 7);
+        assert_eq!(st.messages, 4);
+        assert_eq!(st.duplicates, 3);
+        assert_eq!(st.heartbeats, 1);
+        assert_eq!((st.gaps, st.missing), (1, 1));
+        assert_eq!(st.rejected, 1);
+
+        let msgs = fh.writer();
+        assert_eq!(msgs.len(), 4);
+        let trk: Vec<u16> = msgs
+            .iter()
+            .map(|m| from_bytes(m.data()).unwrap().tracking)
+            .collect();
+        assert_eq!(trk, vec![1, 2, 3, 5]);
+        assert_eq!(msgs[3].data(), &to_bytes(&add_order(5)).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_feed_series() {
+        let name = &test_series_name("feed_test");
+        let writer = MdCacheWriter::create(name, 16, 1).unwrap();
+        let fpath = writer.path().to_owned();
+        let mut fh = FeedHandler::new(UdpSocket::bind("127.0.0.1:0").unwrap(), writer);
+        let mut pb = PacketBuilder::new("SHFE01", 1, MOLD_MTU);
+        for i in 1..=3 {
+            pb.push_message(&add_order(i)).unwrap();
+        }
+        assert_eq!(fh.handle_packet(pb.packet()), 3);
+        let md = MdCache::open(name).unwrap();
+        assert_eq!(md.len(), 3);
+        assert_eq!(md.msgs()[2].data(), &to_bytes(&add_order(3)).unwrap()[..]);
+        _ = std::fs::remove_file(fpath);
+    }
+
+    #[test]
+    fn test_unaligned_symbol_directory() {
+        let sd = Message {
+            index: 2,
+            tracking: 1,
+            timestamp: 1000,
+            body: Body::SymbolDirectory(SymbolDirectory {
+                symbol: "cu2206".to_owned(),
+                market_category: b'F',
+                classification: b'F',
+                precision: 0,
+                round_lot_size: 1,
+                turnover_multi: 5,
+                lower_limit: 60000,
+                upper_limit: 70000,
+            }),
+        };
+        let raw = to_bytes(&sd).unwrap();
+        let mut pb = PacketBuilder::new("SHFE01", 1, MOLD_MTU);
+        // 1 byte message ahead puts 'R' at an odd offset of the packet
+        pb.push(b"Z");
+        pb.push(&raw);
+        let mut fh = FeedHandler::new(UdpSocket::bind("127.0.0.1:0").unwrap(), Vec::new());
+        assert_eq!(fh.handle_packet(pb.packet()), 1);
+        assert_eq!(fh.stats().rejected, 1);
+        let msg = from_bytes(fh.writer()[0].data()).unwrap();
+        assert_eq!(msg, sd);
+    }
+}
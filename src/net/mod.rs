@@ -0,0 +1,15 @@
+//! net - transport framing and sessions for PITCH feeds
+//!
+//! MoldUDP64 packets carry sequenced PITCH messages over UDP multicast,
+//! both as in-memory framer/deframer and socket helpers. SoupBinTCP
+//! sessions serve recovery and replay of a series over TCP. The feed
+//! handler appends a UDP feed to a sink of messages, e.g. an MdCache
+//! series.
+
+mod feed;
+mod moldudp64;
+mod soupbintcp;
+
+pub use feed::{FeedHandler, FeedStats};
+pub use moldudp64::*;
+pub use soupbintcp::*;
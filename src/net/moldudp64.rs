@@ -0,0 +1,322 @@
+//! moldudp64 - MoldUDP64 packet framing
+//!
+//! Packet header is 10 bytes session, u64 sequence number of the first
+//! message and u16 message count, all big endian. Each message block
+//! is u16 length followed by the message bytes. A count of 0 is a
+//! heartbeat, 0xFFFF marks end of session.
+
+use crate::pitch::{to_bytes as pitch_to_bytes, Message};
+use crate::serde::{Error, Result};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+pub const SESSION_LEN: usize = 10;
+pub const MOLD_HEADER_LEN: usize = 20;
+/// message count of end of session packet
+pub const END_OF_SESSION: u16 = 0xFFFF;
+/// max payload of UDP over ethernet without fragmentation
+pub const MOLD_MTU: usize = 1472;
+
+pub type Session = [u8; SESSION_LEN];
+
+/// Session id padded with spaces, truncated to 10 bytes
+pub fn session_id(s: &str) -> Session {
+    let mut res = [b' '; SESSION_LEN];
+    let sb = s.as_bytes();
+    let ll = if sb.len() > SESSION_LEN {
+        SESSION_LEN
+    } else {
+        sb.len()
+    };
+    res[..ll].copy_from_slice(&sb[..ll]);
+    res
+}
+
+fn be_u16(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn be_u64(b: &[u8]) -> u64 {
+    let mut v = [0u8; 8];
+    v.copy_from_slice(&b[..8]);
+    u64::from_be_bytes(v)
+}
+
+/// A validated MoldUDP64 packet borrowed from the receive buffer
+#[derive(Copy, Clone)]
+pub struct MoldPacket<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> MoldPacket<'a> {
+    /// Validate header and that all message blocks fit in buf
+    pub fn parse(buf: &'a [u8]) -> Result<MoldPacket<'a>> {
+        if buf.len() < MOLD_HEADER_LEN {
+            return Err(Error::Eof);
+        }
+        let pkt = MoldPacket { buf };
+        let cnt = if pkt.is_end_of_session() {
+            0
+        } else {
+            pkt.count()
+        };
+        if pkt.sequence().checked_add(cnt as u64).is_none() {
+            return Err(Error::Message("sequence overflow".to_owned()));
+        }
+        let mut off = MOLD_HEADER_LEN;
+        for _ in 0..cnt {
+            if buf.len() < off + 2 {
+                return Err(Error::Eof);
+            }
+            off += 2 + be_u16(&buf[off..]) as usize;
+            if buf.len() < off {
+                return Err(Error::Eof);
+            }
+        }
+        if off != buf.len() {
+            return Err(Error::TrailingCharacters);
+        }
+        Ok(MoldPacket { buf: &buf[..off] })
+    }
+    pub fn session(&self) -> &'a [u8] {
+        &self.buf[..SESSION_LEN]
+    }
+    /// Sequence number of the first message
+    pub fn sequence(&self) -> u64 {
+        be_u64(&self.buf[SESSION_LEN..])
+    }
+    /// Raw message count field
+    pub fn count(&self) -> u16 {
+        be_u16(&self.buf[SESSION_LEN + 8..])
+    }
+    pub fn is_heartbeat(&self) -> bool {
+        self.count() == 0
+    }
+    pub fn is_end_of_session(&self) -> bool {
+        self.count() == END_OF_SESSION
+    }
+    /// Sequence number expected of the next packet
+    pub fn next_sequence(&self) -> u64 {
+        if self.is_end_of_session() {
+            self.sequence()
+        } else {
+            // no overflow, checked by parse
+            self.sequence() + self.count() as u64
+        }
+    }
+    pub fn messages(&self) -> MoldMessages<'a> {
+        MoldMessages {
+            buf: &self.buf[MOLD_HEADER_LEN..],
+        }
+    }
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+
+impl<'a> fmt::Display for MoldPacket<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "(session: {}, seq: {}, count: {})",
+            String::from_utf8_lossy(self.session()),
+            self.sequence(),
+            self.count()
+        )
+    }
+}
+
+/// Iterator over message slices of a packet
+pub struct MoldMessages<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for MoldMessages<'a> {
+    type Item = &'a [u8];
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.buf.len() < 2 {
+            return None;
+        }
+        let ll = be_u16(self.buf) as usize;
+        let msg = &self.buf[2..2 + ll];
+        self.buf = &self.buf[2 + ll..];
+        Some(msg)
+    }
+}
+
+/// Builds packets of messages, sequence advances as packets are cleared
+pub struct PacketBuilder {
+    buf: Vec<u8>,
+    session: Session,
+    seq: u64,
+    count: u16,
+    mtu: usize,
+}
+
+impl PacketBuilder {
+    pub fn new(session: &str, seq: u64, mtu: usize) -> PacketBuilder {
+        let mut buf = Vec::with_capacity(mtu);
+        buf.resize(MOLD_HEADER_LEN, 0);
+        PacketBuilder {
+            buf,
+            session: session_id(session),
+            seq,
+            count: 0,
+            mtu,
+        }
+    }
+    /// Sequence number of the first message in current packet
+    pub fn sequence(&self) -> u64 {
+        self.seq
+    }
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+    /// Append message, false if packet has no room left, msg is longer
+    /// than u16::MAX or sequence would overflow
+    pub fn push(&mut self, msg: &[u8]) -> bool {
+        if msg.len() > u16::MAX as usize
+            || self.buf.len() + 2 + msg.len() > self.mtu
+            || self.count >= END_OF_SESSION - 1
+            || self.seq.checked_add(self.count as u64 + 1).is_none()
+        {
+            return false;
+        }
+        self.buf
+            .extend_from_slice(&(msg.len() as u16).to_be_bytes());
+        self.buf.extend_from_slice(msg);
+        self.count += 1;
+        true
+    }
+    /// Encode PITCH message and append, false if packet has no room left
+    pub fn push_message(&mut self, msg: &Message) -> Result<bool> {
+        let buf = pitch_to_bytes(msg)?;
+        Ok(self.push(&buf))
+    }
+    fn header(&self, count: u16) -> [u8; MOLD_HEADER_LEN] {
+        let mut hdr = [0u8; MOLD_HEADER_LEN];
+        hdr[..SESSION_LEN].copy_from_slice(&self.session);
+        hdr[SESSION_LEN..SESSION_LEN + 8].copy_from_slice(&self.seq.to_be_bytes());
+        hdr[SESSION_LEN + 8..].copy_from_slice(&count.to_be_bytes());
+        hdr
+    }
+    /// Packet bytes of messages pushed
+    pub fn packet(&mut self) -> &[u8] {
+        let hdr = self.header(self.count);
+        self.buf[..MOLD_HEADER_LEN].copy_from_slice(&hdr);
+        &self.buf
+    }
+    /// Start next packet, sequence advances by messages sent, push
+    /// keeps it from overflow
+    pub fn clear(&mut self) {
+        self.seq = self.seq.saturating_add(self.count as u64);
+        self.count = 0;
+        self.buf.truncate(MOLD_HEADER_LEN);
+    }
+    pub fn heartbeat(&self) -> [u8; MOLD_HEADER_LEN] {
+        self.header(0)
+    }
+    pub fn end_of_session(&self) -> [u8; MOLD_HEADER_LEN] {
+        self.header(END_OF_SESSION)
+    }
+}
+
+/// Send packet of builder then start next packet
+pub fn send_packet(sock: &UdpSocket, addr: &SocketAddr, pb: &mut PacketBuilder) -> io::Result<()> {
+    if pb.is_empty() {
+        return Ok(());
+    }
+    sock.send_to(pb.packet(), addr)?;
+    pb.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{from_bytes, AddOrder, Body, Side};
+
+    fn add_order(tracking: u16) -> Message {
+        Message {
+            index: 1,
+            tracking,
+            timestamp: 123456,
+            body: Body::AddOrder(AddOrder {
+                reference: tracking as u64,
+                side: Side::Buy,
+                qty: 100,
+                price: 51050,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_mold_packet() {
+        let mut pb = PacketBuilder::new("SHFE01", 1, 100);
+        assert!(pb.push_message(&add_order(1)).unwrap());
+        assert!(pb.push_message(&add_order(2)).unwrap());
+        // 20 + 2 * 28 + 28 > 100
+        assert!(!pb.push_message(&add_order(3)).unwrap());
+        let buf = pb.packet().to_vec();
+        let pkt = MoldPacket::parse(&buf).unwrap();
+        println!("packet: {}", pkt);
+        assert_eq!(pkt.session(), b"SHFE01    ");
+        assert_eq!(pkt.sequence(), 1);
+        assert_eq!(pkt.count(), 2);
+        assert_eq!(pkt.next_sequence(), 3);
+        let msgs: Vec<Message> = pkt.messages().map(|m| from_bytes(m).unwrap()).collect();
+        assert_eq!(msgs, vec![add_order(1), add_order(2)]);
+        pb.clear();
+        assert_eq!(pb.sequence(), 3);
+        let hb = pb.heartbeat();
+        let pkt = MoldPacket::parse(&hb).unwrap();
+        assert!(pkt.is_heartbeat());
+        assert_eq!(pkt.sequence(), 3);
+        let eos = pb.end_of_session();
+        let pkt = MoldPacket::parse(&eos).unwrap();
+        assert!(pkt.is_end_of_session());
+        assert_eq!(pkt.messages().count(), 0);
+        assert!(MoldPacket::parse(&buf[..buf.len() - 1]).is_err());
+        assert!(MoldPacket::parse(&buf[..10]).is_err());
+        // hostile sequence near u64::MAX
+        let mut pb = PacketBuilder::new("SHFE01", u64::MAX - 1, MOLD_MTU);
+        assert!(pb.push(b"A"));
+        assert!(!pb.push(b"B"));
+        let mut buf = pb.packet().to_vec();
+        assert!(MoldPacket::parse(&buf).is_ok());
+        pb.clear();
+        assert_eq!(pb.sequence(), u64::MAX);
+        // count 2 claimed, 2nd message past u64::MAX
+        buf[SESSION_LEN + 8..MOLD_HEADER_LEN].copy_from_slice(&2u16.to_be_bytes());
+        buf.extend_from_slice(&[0, 1, b'B']);
+        assert!(MoldPacket::parse(&buf).is_err());
+        let mut pb = PacketBuilder::new("SHFE01", 1, 1 << 20);
+        assert!(!pb.push(&vec![0u8; 70000]));
+        assert!(pb.push(&vec![0u8; 65535]));
+    }
+
+    #[test]
+    fn test_mold_udp() {
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = rx.local_addr().unwrap();
+        let mut pb = PacketBuilder::new("SHFE01", 1, MOLD_MTU);
+        for i in 1..=10 {
+            assert!(pb.push_message(&add_order(i)).unwrap());
+        }
+        send_packet(&tx, &addr, &mut pb).unwrap();
+        assert_eq!(pb.sequence(), 11);
+        let mut buf = [0u8; MOLD_MTU];
+        let (ll, _) = rx.recv_from(&mut buf).unwrap();
+        let pkt = MoldPacket::parse(&buf[..ll]).unwrap();
+        assert_eq!(pkt.count(), 10);
+        for (i, m) in pkt.messages().enumerate() {
+            let msg = from_bytes(m).unwrap();
+            assert_eq!(msg.tracking, i as u16 + 1);
+        }
+    }
+}
@@ -0,0 +1,547 @@
+//! soupbintcp - SoupBinTCP session layer for PITCH recovery and replay
+//!
+//! Every packet is a big endian u16 length, counting the type byte and
+//! payload, followed by the packet type. Sequenced data carry one PITCH
+//! message, numbered from 1 in the session, the sequence number is
+//! implied by the order of packets after login.
+
+use super::moldudp64::{session_id, Session, SESSION_LEN};
+use crate::mdcache::MdCache;
+use crate::serde::{Error, Result};
+use crate::ClMessage;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+const USERNAME_LEN: usize = 6;
+const PASSWORD_LEN: usize = 10;
+const SEQ_LEN: usize = 20;
+const LOGIN_REQ_LEN: usize = USERNAME_LEN + PASSWORD_LEN + SESSION_LEN + SEQ_LEN;
+const LOGIN_ACC_LEN: usize = SESSION_LEN + SEQ_LEN;
+/// max payload of a packet, length field is u16
+const MAX_PAYLOAD: usize = 65534;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_CHUNK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectCode {
+    NotAuthorized = b'A' as isize,
+    SessionNotAvailable = b'S' as isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoupPacket<'a> {
+    Debug(&'a [u8]),
+    LoginAccepted {
+        session: Session,
+        sequence: u64,
+    },
+    LoginRejected(RejectCode),
+    SequencedData(&'a [u8]),
+    UnsequencedData(&'a [u8]),
+    ServerHeartbeat,
+    EndOfSession,
+    LoginRequest {
+        username: [u8; USERNAME_LEN],
+        password: [u8; PASSWORD_LEN],
+        session: Session,
+        sequence: u64,
+    },
+    ClientHeartbeat,
+    LogoutRequest,
+}
+
+// alpha fields are left justified, padded with spaces
+fn alpha<const N: usize>(s: &str) -> [u8; N] {
+    let mut res = [b' '; N];
+    let sb = s.as_bytes();
+    let ll = if sb.len() > N { N } else { sb.len() };
+    res[..ll].copy_from_slice(&sb[..ll]);
+    res
+}
+
+// numeric fields are right justified, padded with spaces
+fn numeric(v: u64) -> [u8; SEQ_LEN] {
+    let ss = format!("{:>20}", v);
+    let mut res = [b' '; SEQ_LEN];
+    res.copy_from_slice(ss.as_bytes());
+    res
+}
+
+fn parse_numeric(b: &[u8]) -> Result<u64> {
+    let ss = std::str::from_utf8(b).map_err(|_| Error::ExpectedInteger)?;
+    let ss = ss.trim();
+    if ss.is_empty() {
+        return Ok(0);
+    }
+    ss.parse::<u64>().map_err(|_| Error::ExpectedInteger)
+}
+
+impl<'a> SoupPacket<'a> {
+    /// Parse one packet of buf, return the packet and bytes consumed,
+    /// Error::Eof if buf holds no complete packet
+    pub fn parse(buf: &'a [u8]) -> Result<(SoupPacket<'a>, usize)> {
+        if buf.len() < 3 {
+            return Err(Error::Eof);
+        }
+        let ll = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        if ll == 0 {
+            return Err(Error::Syntax);
+        }
+        if buf.len() < ll + 2 {
+            return Err(Error::Eof);
+        }
+        let payload = &buf[3..ll + 2];
+        let pkt = match buf[2] {
+            b'+' => SoupPacket::Debug(payload),
+            b'A' => {
+                if payload.len() != LOGIN_ACC_LEN {
+                    return Err(Error::Syntax);
+                }
+                let mut session: Session = Default::default();
+                session.copy_from_slice(&payload[..SESSION_LEN]);
+                let sequence = parse_numeric(&payload[SESSION_LEN..])?;
+                SoupPacket::LoginAccepted { session, sequence }
+            }
+            b'J' => match payload.first() {
+                Some(b'A') => SoupPacket::LoginRejected(RejectCode::NotAuthorized),
+                Some(b'S') => SoupPacket::LoginRejected(RejectCode::SessionNotAvailable),
+                _ => return Err(Error::Syntax),
+            },
+            b'S' => SoupPacket::SequencedData(payload),
+            b'U' => SoupPacket::UnsequencedData(payload),
+            b'H' => SoupPacket::ServerHeartbeat,
+            b'Z' => SoupPacket::EndOfSession,
+            b'L' => {
+                if payload.len() != LOGIN_REQ_LEN {
+                    return Err(Error::Syntax);
+                }
+                let mut username = [0u8; USERNAME_LEN];
+                let mut password = [0u8; PASSWORD_LEN];
+                let mut session: Session = Default::default();
+                let (u, rest) = payload.split_at(USERNAME_LEN);
+                let (p, rest) = rest.split_at(PASSWORD_LEN);
+                let (s, rest) = rest.split_at(SESSION_LEN);
+                username.copy_from_slice(u);
+                password.copy_from_slice(p);
+                session.copy_from_slice(s);
+                let sequence = parse_numeric(rest)?;
+                SoupPacket::LoginRequest {
+                    username,
+                    password,
+                    session,
+                    sequence,
+                }
+            }
+            b'R' => SoupPacket::ClientHeartbeat,
+            b'O' => SoupPacket::LogoutRequest,
+            _ => return Err(Error::Syntax),
+        };
+        Ok((pkt, ll + 2))
+    }
+    /// Append encoded packet to out
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<()> {
+        let start = out.len();
+        out.extend_from_slice(&[0, 0]);
+        match self {
+            SoupPacket::Debug(s) => {
+                out.push(b'+');
+                out.extend_from_slice(s);
+            }
+            SoupPacket::LoginAccepted { session, sequence } => {
+                out.push(b'A');
+                out.extend_from_slice(session);
+                out.extend_from_slice(&numeric(*sequence));
+            }
+            SoupPacket::LoginRejected(r) => {
+                out.push(b'J');
+                out.push(*r as u8);
+            }
+            SoupPacket::SequencedData(s) => {
+                out.push(b'S');
+                out.extend_from_slice(s);
+            }
+            SoupPacket::UnsequencedData(s) => {
+                out.push(b'U');
+                out.extend_from_slice(s);
+            }
+            SoupPacket::ServerHeartbeat => out.push(b'H'),
+            SoupPacket::EndOfSession => out.push(b'Z'),
+            SoupPacket::LoginRequest {
+                username,
+                password,
+                session,
+                sequence,
+            } => {
+                out.push(b'L');
+                out.extend_from_slice(username);
+                out.extend_from_slice(password);
+                out.extend_from_slice(session);
+                out.extend_from_slice(&numeric(*sequence));
+            }
+            SoupPacket::ClientHeartbeat => out.push(b'R'),
+            SoupPacket::LogoutRequest => out.push(b'O'),
+        }
+        let ll = out.len() - start - 2;
+        if ll > MAX_PAYLOAD + 1 {
+            out.truncate(start);
+            return Err(Error::NoBufs);
+        }
+        out[start..start + 2].copy_from_slice(&(ll as u16).to_be_bytes());
+        Ok(())
+    }
+}
+
+fn invalid_data(e: Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Packet stream over a TCP connection
+pub struct SoupConn {
+    stream: TcpStream,
+    rbuf: Vec<u8>,
+    /// bytes of rbuf taken by the packet last returned
+    consumed: usize,
+    wbuf: Vec<u8>,
+}
+
+impl SoupConn {
+    pub fn new(stream: TcpStream) -> SoupConn {
+        _ = stream.set_nodelay(true);
+        SoupConn {
+            stream,
+            rbuf: Vec::with_capacity(MAX_PAYLOAD + 3),
+            consumed: 0,
+            wbuf: Vec::with_capacity(256),
+        }
+    }
+    pub fn send(&mut self, pkt: &SoupPacket) -> io::Result<()> {
+        self.wbuf.clear();
+        pkt.encode(&mut self.wbuf).map_err(invalid_data)?;
+        self.stream.write_all(&self.wbuf)
+    }
+    /// Blocking read of next packet, bytes of a partial packet are kept
+    /// across a read timeout
+    pub fn recv(&mut self) -> io::Result<SoupPacket<'_>> {
+        self.rbuf.drain(..self.consumed);
+        self.consumed = 0;
+        let used = loop {
+            match SoupPacket::parse(&self.rbuf) {
+                Ok((_, used)) => break used,
+                Err(Error::Eof) => (),
+                Err(e) => return Err(invalid_data(e)),
+            }
+            let off = self.rbuf.len();
+            self.rbuf.resize(off + READ_CHUNK, 0);
+            let res = self.stream.read(&mut self.rbuf[off..]);
+            self.rbuf.truncate(off + *res.as_ref().unwrap_or(&0));
+            if res? == 0 {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof));
+            }
+        };
+        self.consumed = used;
+        let (pkt, _) = SoupPacket::parse(&self.rbuf[..used]).map_err(invalid_data)?;
+        Ok(pkt)
+    }
+    pub fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(dur)
+    }
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+}
+
+/// Client side of a session, tracks the next sequence number
+pub struct SoupClient {
+    conn: SoupConn,
+    session: Session,
+    next_seq: u64,
+}
+
+impl SoupClient {
+    /// Connect and login, sequence 0 requests the most recent message,
+    /// blank session the current session of server
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        username: &str,
+        password: &str,
+        session: &str,
+        sequence: u64,
+    ) -> io::Result<SoupClient> {
+        let mut conn = SoupConn::new(TcpStream::connect(addr)?);
+        let login = SoupPacket::LoginRequest {
+            username: alpha(username),
+            password: alpha(password),
+            session: session_id(session),
+            sequence,
+        };
+        conn.send(&login)?;
+        let (session, next_seq) = match conn.recv()? {
+            SoupPacket::LoginAccepted { session, sequence } => (session, sequence),
+            SoupPacket::LoginRejected(r) => {
+                let msg = format!("login rejected: {:?}", r);
+                return Err(io::Error::new(ErrorKind::PermissionDenied, msg));
+            }
+            _ => return Err(io::Error::from(ErrorKind::InvalidData)),
+        };
+        Ok(SoupClient {
+            conn,
+            session,
+            next_seq,
+        })
+    }
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+    /// Sequence number of the next sequenced data packet
+    pub fn next_sequence(&self) -> u64 {
+        self.next_seq
+    }
+    pub fn recv(&mut self) -> io::Result<SoupPacket<'_>> {
+        let pkt = self.conn.recv()?;
+        if let SoupPacket::SequencedData(_) = pkt {
+            self.next_seq += 1;
+        }
+        Ok(pkt)
+    }
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        self.conn.send(&SoupPacket::ClientHeartbeat)
+    }
+    pub fn send_unsequenced(&mut self, data: &[u8]) -> io::Result<()> {
+        self.conn.send(&SoupPacket::UnsequencedData(data))
+    }
+    pub fn logout(mut self) -> io::Result<()> {
+        self.conn.send(&SoupPacket::LogoutRequest)?;
+        self.conn.shutdown()
+    }
+}
+
+/// Server side, serves sequenced data out of `ClMessage` series
+pub struct SoupServer {
+    session: Session,
+    auth: Option<([u8; USERNAME_LEN], [u8; PASSWORD_LEN])>,
+    login_timeout: Duration,
+}
+
+impl SoupServer {
+    pub fn new(session: &str) -> SoupServer {
+        SoupServer {
+            session: session_id(session),
+            auth: None,
+            login_timeout: LOGIN_TIMEOUT,
+        }
+    }
+    /// Drop client not sending LoginRequest within `dur`
+    pub fn with_login_timeout(mut self, dur: Duration) -> SoupServer {
+        self.login_timeout = dur;
+        self
+    }
+    /// Require username and password for login
+    pub fn with_auth(mut self, username: &str, password: &str) -> SoupServer {
+        self.auth = Some((alpha(username), alpha(password)));
+        self
+    }
+    /// Serve one client with msgs, end of session after the last message
+    pub fn serve(&self, stream: TcpStream, msgs: &[ClMessage]) -> io::Result<u64> {
+        self.serve_source(stream, msgs, &|| msgs.len(), &|| true)
+    }
+    /// Serve one client out of MdCache, follow new messages until the
+    /// series is shut down
+    pub fn serve_mdcache(&self, stream: TcpStream, md: &MdCache) -> io::Result<u64> {
+        let avail = || md.len();
+        let ended = || md.header().shut_time != 0;
+        self.serve_source(stream, md.msgs(), &avail, &ended)
+    }
+    fn login(&self, conn: &mut SoupConn, avail: usize) -> io::Result<Option<u64>> {
+        let (username, password, session, sequence) = match conn.recv()? {
+            SoupPacket::LoginRequest {
+                username,
+                password,
+                session,
+                sequence,
+            } => (username, password, session, sequence),
+            _ => return Err(io::Error::from(ErrorKind::InvalidData)),
+        };
+        if let Some((u, p)) = &self.auth {
+            if *u != username || *p != password {
+                conn.send(&SoupPacket::LoginRejected(RejectCode::NotAuthorized))?;
+                return Ok(None);
+            }
+        }
+        if session != [b' '; SESSION_LEN] && session != self.session {
+            conn.send(&SoupPacket::LoginRejected(RejectCode::SessionNotAvailable))?;
+            return Ok(None);
+        }
+        let last = avail as u64 + 1;
+        let sequence = if sequence == 0 || sequence > last {
+            last
+        } else {
+            sequence
+        };
+        let session = self.session;
+        conn.send(&SoupPacket::LoginAccepted { session, sequence })?;
+        Ok(Some(sequence))
+    }
+    fn serve_source(
+        &self,
+        stream: TcpStream,
+        msgs: &[ClMessage],
+        avail: &dyn Fn() -> usize,
+        ended: &dyn Fn() -> bool,
+    ) -> io::Result<u64> {
+        let mut conn = SoupConn::new(stream);
+        conn.set_read_timeout(Some(self.login_timeout))?;
+        let mut next = match self.login(&mut conn, avail())? {
+            Some(seq) => (seq - 1) as usize,
+            None => return Ok(0),
+        };
+        conn.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut sent = 0u64;
+        let mut last_send = Instant::now();
+        let mut last_recv = Instant::now();
+        let mut eos = false;
+        loop {
+            let cnt = avail();
+            while next < cnt && next < msgs.len() {
+                conn.send(&SoupPacket::SequencedData(msgs[next].data()))?;
+                next += 1;
+                sent += 1;
+                last_send = Instant::now();
+            }
+            if !eos && ended() && next >= avail() {
+                conn.send(&SoupPacket::EndOfSession)?;
+                eos = true;
+            }
+            if last_send.elapsed() >= HEARTBEAT_INTERVAL {
+                conn.send(&SoupPacket::ServerHeartbeat)?;
+                last_send = Instant::now();
+            }
+            match conn.recv() {
+                Ok(SoupPacket::LogoutRequest) => break,
+                Ok(_) => last_recv = Instant::now(),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    if last_recv.elapsed() >= IDLE_TIMEOUT {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        _ = conn.shutdown();
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::from_bytes;
+    use crate::testutil::add_orders;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn series(cnt: u16) -> Vec<ClMessage> {
+        add_orders(1..=cnt as u32, |i| (1, i as u16, 100))
+    }
+
+    #[test]
+    fn test_soup_packet() {
+        let mut buf = Vec::new();
+        let login = SoupPacket::LoginRequest {
+            username: alpha("user"),
+            password: alpha("pass"),
+            session: session_id("SHFE01"),
+            sequence: 12345,
+        };
+        login.encode(&mut buf).unwrap();
+        assert_eq!(buf.len(), 2 + 1 + LOGIN_REQ_LEN);
+        SoupPacket::SequencedData(b"abc").encode(&mut buf).unwrap();
+        SoupPacket::EndOfSession.encode(&mut buf).unwrap();
+        let (pkt, ll) = SoupPacket::parse(&buf).unwrap();
+        assert_eq!(pkt, login);
+        let (pkt, ll1) = SoupPacket::parse(&buf[ll..]).unwrap();
+        assert_eq!(pkt, SoupPacket::SequencedData(b"abc"));
+        let (pkt, _) = SoupPacket::parse(&buf[ll + ll1..]).unwrap();
+        assert_eq!(pkt, SoupPacket::EndOfSession);
+        assert!(SoupPacket::parse(&buf[..ll - 1]).is_err());
+    }
+
+    #[test]
+    fn test_soup_session() {
+        let msgs = series(10);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let srv_msgs = msgs.clone();
+        let srv = thread::spawn(move || {
+            let server = SoupServer::new("SHFE01").with_auth("user", "pass");
+            let mut sent = Vec::new();
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                sent.push(server.serve(stream, &srv_msgs).unwrap());
+            }
+            sent
+        });
+        let mut cl = SoupClient::connect(addr, "user", "pass", "", 4).unwrap();
+        assert_eq!(cl.session(), &session_id("SHFE01"));
+        assert_eq!(cl.next_sequence(), 4);
+        let mut cnt = 0;
+        loop {
+            let seq = cl.next_sequence();
+            match cl.recv().unwrap() {
+                SoupPacket::SequencedData(d) => {
+                    let msg = from_bytes(d).unwrap();
+                    assert_eq!(msg.tracking as u64, seq);
+                    cnt += 1;
+                }
+                SoupPacket::EndOfSession => break,
+                _ => {}
+            }
+        }
+        assert_eq!(cnt, 7);
+        cl.logout().unwrap();
+        let err = SoupClient::connect(addr, "user", "bad", "", 1)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        let err = SoupClient::connect(addr, "user", "pass", "DCE", 1)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(srv.join().unwrap(), vec![7, 0, 0]);
+    }
+
+    #[test]
+    fn test_partial_packet() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut conn = SoupConn::new(stream);
+        conn.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+        let mut buf = Vec::new();
+        SoupPacket::SequencedData(b"hello")
+            .encode(&mut buf)
+            .unwrap();
+        SoupPacket::ServerHeartbeat.encode(&mut buf).unwrap();
+        // timeout in the middle of a packet
+        peer.write_all(&buf[..4]).unwrap();
+        let err = conn.recv().err().unwrap();
+        assert!(err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut);
+        peer.write_all(&buf[4..]).unwrap();
+        assert_eq!(conn.recv().unwrap(), SoupPacket::SequencedData(b"hello"));
+        assert_eq!(conn.recv().unwrap(), SoupPacket::ServerHeartbeat);
+    }
+
+    #[test]
+    fn test_login_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let server = SoupServer::new("SHFE01").with_login_timeout(Duration::from_millis(50));
+        let err = server.serve(stream, &series(1)).err().unwrap();
+        assert!(err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut);
+    }
+}
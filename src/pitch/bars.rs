@@ -0,0 +1,494 @@
+//! bars - OHLCV bars aggregated from PITCH trades
+//!
+//! Trade, printable OrderExecutedWithPrice and CrossTrade messages are
+//! aggregated per instrument. Clock bars are aligned to multiples of the
+//! interval in local time, or UTC for `BarBuilder<true>`; intervals
+//! without trades produce no bar. Turnover is price * qty * turnover_multi
+//! of the SymbolDirectory, in units of the price precision.
+//!
+//! Event bars close on the trade reaching the threshold of trades, lots
+//! or turnover, trades are not split across bars. Bars encode into 64
+//! bytes `ClMessage` via the crate serde.
+
+use super::clock::MsgClock;
+use super::enums::EventCode;
+use super::pitch::{Body, Message};
+use super::registry::SymbolRegistry;
+use crate::serde::Result;
+use crate::{from_msg, to_msg, ClMessage, DateTime, Local, TimeVal};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+const MICROS: u64 = 1_000_000;
+
+/// message tag of encoded bar
+pub const BAR_TAG: u8 = b'b';
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BarInterval {
+    Seconds(u32),
+    /// one bar per market session, closed by EndOfMarketHours
+    Session,
+}
+
+impl BarInterval {
+    pub const SECOND: BarInterval = BarInterval::Seconds(1);
+    pub const MINUTE: BarInterval = BarInterval::Seconds(60);
+    pub const FIVE_MINUTES: BarInterval = BarInterval::Seconds(300);
+}
+
+impl fmt::Display for BarInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarInterval::Seconds(n) if n % 60 == 0 => write!(f, "{}m", n / 60),
+            BarInterval::Seconds(n) => write!(f, "{}s", n),
+            BarInterval::Session => write!(f, "Session"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BarPolicy {
+    Time(BarInterval),
+    /// every N trades
+    Ticks(u32),
+    /// every N lots
+    Volume(u32),
+    /// every N of turnover
+    Turnover(i64),
+}
+
+impl BarPolicy {
+    /// `policy` code of bars
+    pub fn code(&self) -> u8 {
+        match self {
+            BarPolicy::Time(BarInterval::Seconds(_)) => b'T',
+            BarPolicy::Time(BarInterval::Session) => b'S',
+            BarPolicy::Ticks(_) => b'N',
+            BarPolicy::Volume(_) => b'V',
+            BarPolicy::Turnover(_) => b'M',
+        }
+    }
+    // bar ends at its last trade rather than a clock boundary
+    fn ends_at_last(&self) -> bool {
+        !matches!(self, BarPolicy::Time(BarInterval::Seconds(_)))
+    }
+}
+
+impl From<BarInterval> for BarPolicy {
+    fn from(v: BarInterval) -> BarPolicy {
+        BarPolicy::Time(v)
+    }
+}
+
+impl fmt::Display for BarPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarPolicy::Time(v) => write!(f, "{}", v),
+            BarPolicy::Ticks(n) => write!(f, "{} trades", n),
+            BarPolicy::Volume(n) => write!(f, "{} lots", n),
+            BarPolicy::Turnover(n) => write!(f, "{} turnover", n),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Bar {
+    pub tag: u8,
+    /// `BarPolicy::code` of the builder
+    pub policy: u8,
+    pub index: u16,
+    /// trades aggregated
+    pub count: u32,
+    /// micros since epoch, bar boundaries of clock bars, first and last
+    /// trade otherwise
+    pub start: u64,
+    pub end: u64,
+    pub open: i32,
+    pub high: i32,
+    pub low: i32,
+    pub close: i32,
+    pub volume: u32,
+    pub turnover: i64,
+    /// open interest of the latest CrossTrade, 0 if none
+    pub open_interest: u32,
+    /// volume weighted average price
+    pub vwap: i32,
+}
+
+impl Bar {
+    fn new(policy: u8, index: u16, start: u64, end: u64, price: i32) -> Bar {
+        Bar {
+            tag: BAR_TAG,
+            policy,
+            index,
+            start,
+            end,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            ..Default::default()
+        }
+    }
+    pub fn start_time(&self) -> TimeVal {
+        micros_timeval(self.start)
+    }
+    pub fn end_time(&self) -> TimeVal {
+        micros_timeval(self.end)
+    }
+    /// Start as DateTime in micros, local or UTC by IS_UTC
+    pub fn start_datetime<const IS_UTC: bool>(&self) -> DateTime<1_000_000, IS_UTC> {
+        DateTime::new(self.start as i64)
+    }
+    pub fn to_msg(&self) -> Result<ClMessage> {
+        to_msg(self)
+    }
+    pub fn from_msg(msg: &ClMessage) -> Result<Bar> {
+        from_msg(msg)
+    }
+}
+
+fn micros_timeval(us: u64) -> TimeVal {
+    TimeVal::new(us / MICROS, (us % MICROS) as u32 * 1000)
+}
+
+impl fmt::Display for Bar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "(index: {}, {}, O {} H {} L {} C {} V {} T {} vwap {} trades {})",
+            self.index,
+            self.start_datetime::<false>(),
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.turnover,
+            self.vwap,
+            self.count
+        )
+    }
+}
+
+// bar in progress, notional is sum of price * qty for vwap
+struct OpenBar {
+    bar: Bar,
+    notional: i128,
+    last: u64,
+}
+
+impl OpenBar {
+    fn close(mut self, at_last: bool) -> Bar {
+        if at_last {
+            self.bar.end = self.last;
+        }
+        if self.bar.volume > 0 {
+            self.bar.vwap = (self.notional / self.bar.volume as i128) as i32;
+        }
+        self.bar
+    }
+}
+
+/// Bars of all instruments by one policy, clock bars aligned in local
+/// time or UTC if IS_UTC
+pub struct BarBuilder<const IS_UTC: bool = false> {
+    policy: BarPolicy,
+    clock: MsgClock,
+    registry: SymbolRegistry,
+    open: HashMap<u16, OpenBar>,
+    // earliest end of open bars
+    next_end: u64,
+}
+
+impl<const IS_UTC: bool> BarBuilder<IS_UTC> {
+    /// Builder with hour base hours until the first SystemEvent
+    pub fn new(interval: BarInterval, hours: u32) -> BarBuilder<IS_UTC> {
+        Self::with_policy(BarPolicy::Time(interval), hours)
+    }
+    pub fn with_policy(policy: BarPolicy, hours: u32) -> BarBuilder<IS_UTC> {
+        BarBuilder {
+            policy,
+            clock: MsgClock::new(hours),
+            registry: SymbolRegistry::new(),
+            open: HashMap::new(),
+            next_end: u64::MAX,
+        }
+    }
+    pub fn policy(&self) -> BarPolicy {
+        self.policy
+    }
+    /// Instruments seen, for turnover_multi and price precision
+    pub fn registry(&self) -> &SymbolRegistry {
+        &self.registry
+    }
+    /// Bar in progress of instrument
+    pub fn current(&self, index: u16) -> Option<&Bar> {
+        self.open.get(&index).map(|b| &b.bar)
+    }
+    // bar boundaries around micros
+    fn bounds(&self, us: u64) -> (u64, u64) {
+        match self.policy {
+            BarPolicy::Time(BarInterval::Seconds(n)) => {
+                let n_us = n.max(1) as i64 * MICROS as i64;
+                let off = if IS_UTC {
+                    0
+                } else {
+                    Local::offset() * MICROS as i64
+                };
+                let start = (us as i64 - off).div_euclid(n_us) * n_us + off;
+                (start as u64, (start + n_us) as u64)
+            }
+            _ => (us, u64::MAX),
+        }
+    }
+    /// Apply one PITCH message, append bars closed to bars
+    pub fn update(&mut self, msg: &Message, bars: &mut Vec<Bar>) {
+        let now = self.clock.update(msg);
+        self.close_expired(now, bars);
+        match &msg.body {
+            Body::SymbolDirectory(_) => {
+                self.registry.ingest(msg);
+            }
+            Body::SystemEvent(s) => {
+                if matches!(
+                    s.event,
+                    EventCode::EndOfMarketHours | EventCode::EndOfSystemHours
+                ) {
+                    self.flush(bars);
+                }
+            }
+            Body::Trade(s) => self.trade(msg.index, now, s.price, s.qty, None, bars),
+            Body::OrderExecutedWithPrice(s) if s.printable => {
+                self.trade(msg.index, now, s.price, s.qty, None, bars)
+            }
+            Body::CrossTrade(s) => {
+                let oi = Some(s.open_interest);
+                self.trade(msg.index, now, s.price, s.qty, oi, bars)
+            }
+            _ => (),
+        }
+    }
+    fn trade(
+        &mut self,
+        index: u16,
+        now: u64,
+        price: i32,
+        qty: u32,
+        oi: Option<u32>,
+        bars: &mut Vec<Bar>,
+    ) {
+        let multi = self
+            .registry
+            .get(index)
+            .map_or(1, |inst| inst.turnover_multi.max(1));
+        let (start, end) = self.bounds(now);
+        let code = self.policy.code();
+        let ob = self.open.entry(index).or_insert_with(|| OpenBar {
+            bar: Bar::new(code, index, start, end, price),
+            notional: 0,
+            last: now,
+        });
+        let bar = &mut ob.bar;
+        bar.count += 1;
+        bar.high = bar.high.max(price);
+        bar.low = bar.low.min(price);
+        bar.close = price;
+        bar.volume = bar.volume.saturating_add(qty);
+        let notional = price as i64 * qty as i64;
+        bar.turnover += notional * multi as i64;
+        if let Some(oi) = oi {
+            bar.open_interest = oi;
+        }
+        ob.notional += notional as i128;
+        ob.last = now;
+        let full = match self.policy {
+            BarPolicy::Time(_) => false,
+            BarPolicy::Ticks(n) => bar.count >= n,
+            BarPolicy::Volume(n) => bar.volume >= n,
+            BarPolicy::Turnover(n) => bar.turnover >= n,
+        };
+        if full {
+            self.close_bars(&[index], bars);
+        } else if end < self.next_end {
+            self.next_end = end;
+        }
+    }
+    /// Close bars ending at or before now, e.g. on a timer between messages
+    pub fn close_expired(&mut self, now: u64, bars: &mut Vec<Bar>) {
+        if now < self.next_end {
+            return;
+        }
+        let mut expired: Vec<u16> = self
+            .open
+            .iter()
+            .filter(|(_, b)| b.bar.end <= now)
+            .map(|(k, _)| *k)
+            .collect();
+        expired.sort_unstable();
+        self.close_bars(&expired, bars);
+    }
+    /// Close all bars in progress, e.g. at end of session
+    pub fn flush(&mut self, bars: &mut Vec<Bar>) {
+        let mut all: Vec<u16> = self.open.keys().copied().collect();
+        all.sort_unstable();
+        self.close_bars(&all, bars);
+    }
+    fn close_bars(&mut self, indexes: &[u16], bars: &mut Vec<Bar>) {
+        let at_last = self.policy.ends_at_last();
+        for index in indexes {
+            if let Some(ob) = self.open.remove(index) {
+                bars.push(ob.close(at_last));
+            }
+        }
+        self.next_end = self
+            .open
+            .values()
+            .map(|b| b.bar.end)
+            .min()
+            .unwrap_or(u64::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{CrossTrade, CrossType, Side, SymbolDirectory, SystemEvent, Trade};
+    use crate::testutil::HOURS;
+
+    const T0: u64 = HOURS as u64 * 3_600_000_000;
+
+    fn msg(index: u16, timestamp: u32, body: Body) -> Message {
+        Message {
+            index,
+            tracking: 0,
+            timestamp,
+            body,
+        }
+    }
+
+    fn trade(index: u16, timestamp: u32, price: i32, qty: u32) -> Message {
+        msg(
+            index,
+            timestamp,
+            Body::Trade(Trade {
+                reference: 0,
+                side: Side::Buy,
+                qty,
+                price,
+                match_no: 0,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_minute_bars() {
+        let mut bb = BarBuilder::<true>::new(BarInterval::MINUTE, HOURS);
+        let mut bars = Vec::new();
+        let sd = SymbolDirectory {
+            symbol: "cu2206".to_owned(),
+            market_category: b'F',
+            classification: b'F',
+            precision: 0,
+            round_lot_size: 1,
+            turnover_multi: 5,
+            lower_limit: 0,
+            upper_limit: 0,
+        };
+        bb.update(&msg(1, 0, Body::SymbolDirectory(sd)), &mut bars);
+        bb.update(&trade(1, 1_000_000, 100, 2), &mut bars);
+        bb.update(&trade(1, 20_000_000, 104, 1), &mut bars);
+        bb.update(&trade(2, 30_000_000, 50, 10), &mut bars);
+        bb.update(&trade(1, 59_000_000, 97, 1), &mut bars);
+        assert!(bars.is_empty());
+        assert_eq!(bb.current(1).unwrap().count, 3);
+        // next minute closes bars of both instruments
+        bb.update(&trade(1, 61_000_000, 99, 4), &mut bars);
+        assert_eq!(bars.len(), 2);
+        let b = &bars[0];
+        println!("{}", b);
+        assert_eq!((b.index, b.start, b.end), (1, T0, T0 + 60_000_000));
+        assert_eq!((b.open, b.high, b.low, b.close), (100, 104, 97, 97));
+        assert_eq!((b.volume, b.turnover, b.vwap), (4, 401 * 5, 100));
+        // unknown instrument turnover_multi of 1
+        assert_eq!(bars[1].turnover, 500);
+        assert!(bars[0].start_time() == TimeVal::from_hours(HOURS));
+        let cross = Body::CrossTrade(CrossTrade {
+            qty: 3,
+            price: 98,
+            match_no: 1,
+            cross_type: CrossType::Closing,
+            pclose: 0,
+            open_interest: 1234,
+        });
+        bars.clear();
+        bb.update(&msg(1, 62_000_000, cross), &mut bars);
+        let end = SystemEvent {
+            event: EventCode::EndOfMarketHours,
+            time_hours: HOURS,
+        };
+        bb.update(&msg(0, 63_000_000, Body::SystemEvent(end)), &mut bars);
+        assert_eq!(bars.len(), 1);
+        assert_eq!((bars[0].count, bars[0].open_interest), (2, 1234));
+        assert!(bb.current(1).is_none());
+    }
+
+    #[test]
+    fn test_session_bars() {
+        let mut bb = BarBuilder::<false>::new(BarInterval::Session, HOURS);
+        let mut bars = Vec::new();
+        bb.update(&trade(1, 5_000_000, 10, 1), &mut bars);
+        bb.update(&trade(1, 3_000_000_000, 12, 1), &mut bars);
+        bb.close_expired(T0 + 3_500_000_000, &mut bars);
+        assert!(bars.is_empty());
+        bb.flush(&mut bars);
+        let b = &bars[0];
+        assert_eq!((b.start, b.end), (T0 + 5_000_000, T0 + 3_000_000_000));
+        assert_eq!((b.high, b.low, b.vwap), (12, 10, 11));
+        assert_eq!(BarInterval::FIVE_MINUTES.to_string(), "5m");
+        assert_eq!(b.policy, b'S');
+    }
+
+    #[test]
+    fn test_event_bars() {
+        let mut bars = Vec::new();
+        let mut bb = BarBuilder::<false>::with_policy(BarPolicy::Ticks(3), HOURS);
+        for i in 0..7 {
+            bb.update(&trade(1, i * 1000, 100 + i as i32, 1), &mut bars);
+        }
+        assert_eq!(bars.len(), 2);
+        let b = &bars[1];
+        assert_eq!((b.count, b.open, b.close, b.vwap), (3, 103, 105, 104));
+        assert_eq!((b.start, b.end), (T0 + 3000, T0 + 5000));
+        assert!(b.end_time() == TimeVal::from_hours(HOURS) + 5_000_000);
+        assert_eq!(bb.current(1).unwrap().count, 1);
+
+        bars.clear();
+        let mut bb = BarBuilder::<false>::with_policy(BarPolicy::Volume(10), HOURS);
+        bb.update(&trade(1, 0, 10, 4), &mut bars);
+        bb.update(&trade(1, 1, 20, 8), &mut bars);
+        assert_eq!((bars[0].volume, bars[0].vwap), (12, 16));
+        let mut bb = BarBuilder::<false>::with_policy(BarPolicy::Turnover(1000), HOURS);
+        bb.update(&trade(1, 2, 100, 9), &mut bars);
+        assert_eq!(bars.len(), 1);
+        bb.update(&trade(1, 3, 100, 1), &mut bars);
+        assert_eq!((bars[1].turnover, bars[1].policy), (1000, b'M'));
+
+        let cl = bars[1].to_msg().unwrap();
+        assert_eq!(cl.data()[0], BAR_TAG);
+        assert_eq!(Bar::from_msg(&cl).unwrap(), bars[1]);
+        assert_eq!(BarPolicy::Ticks(3).to_string(), "3 trades");
+    }
+
+    #[test]
+    fn test_local_bounds() {
+        let bb = BarBuilder::<false>::new(BarInterval::Seconds(86400), HOURS);
+        let (start, end) = bb.bounds(T0 + 1);
+        assert_eq!(end - start, 86400 * MICROS);
+        // local midnight
+        let local = start as i64 / MICROS as i64 - Local::offset();
+        assert_eq!(local % 86400, 0);
+    }
+}
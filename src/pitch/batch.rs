@@ -0,0 +1,380 @@
+//! batch - bulk decoding of PITCH messages into columns
+//!
+//! A batch is pre-scanned by tag to group positions of each message type,
+//! each group is then decoded in its own tight loop into a
+//! structure-of-arrays, one column per field. Fields are read through the
+//! zero-copy views, unknown enum codes are kept as `Unknown(u8)`.
+
+use super::enums::{
+    CancelReason, CrossType, EventCode, ImbalanceDirection, MarketParticipantState, Side,
+    TradingState,
+};
+use super::view::*;
+use crate::ClMessage;
+
+macro_rules! columns {
+    ($(#[$m:meta])* $name:ident, $view:ident { $($f:ident: $t:ty),* $(,)? }) => {
+        $(#[$m])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $name {
+            /// position of message in batch
+            pub pos: Vec<u32>,
+            pub index: Vec<u16>,
+            pub tracking: Vec<u16>,
+            pub timestamp: Vec<u32>,
+            $(pub $f: Vec<$t>,)*
+        }
+
+        impl $name {
+            pub fn len(&self) -> usize {
+                self.pos.len()
+            }
+            pub fn is_empty(&self) -> bool {
+                self.pos.is_empty()
+            }
+            fn clear(&mut self) {
+                self.pos.clear();
+                self.index.clear();
+                self.tracking.clear();
+                self.timestamp.clear();
+                $(self.$f.clear();)*
+            }
+            fn reserve(&mut self, n: usize) {
+                self.pos.reserve(n);
+                self.index.reserve(n);
+                self.tracking.reserve(n);
+                self.timestamp.reserve(n);
+                $(self.$f.reserve(n);)*
+            }
+            // decode messages at positions, positions failed go to invalid
+            fn decode<'a, F: Fn(usize) -> &'a [u8]>(
+                &mut self,
+                positions: &[u32],
+                get: &F,
+                invalid: &mut Vec<u32>,
+            ) {
+                self.reserve(positions.len());
+                for &p in positions {
+                    let v = match $view::new(get(p as usize)) {
+                        Ok(v) => v,
+                        Err(_) => {
+                            invalid.push(p);
+                            continue;
+                        }
+                    };
+                    self.pos.push(p);
+                    self.index.push(v.index());
+                    self.tracking.push(v.tracking());
+                    self.timestamp.push(v.timestamp());
+                    $(self.$f.push(v.$f());)*
+                }
+            }
+        }
+    };
+}
+
+columns!(
+    SystemEventCols,
+    SystemEventView {
+        event: EventCode,
+        time_hours: u32,
+    }
+);
+columns!(
+    /// Symbol of a message is decoded from the batch at its pos
+    SymbolDirectoryCols,
+    SymbolDirectoryView {
+        market_category: u8,
+        classification: u8,
+        precision: i8,
+        lot_size: u32,
+        turnover_multi: u32,
+        lower_limit: i32,
+        upper_limit: i32,
+    }
+);
+columns!(
+    TradingActionCols,
+    TradingActionView {
+        trading_state: TradingState,
+        reason: u16,
+    }
+);
+columns!(
+    AddOrderCols,
+    AddOrderView {
+        side: Side,
+        ref_no: u64,
+        qty: u32,
+        price: i32,
+    }
+);
+columns!(
+    OrderExecutedCols,
+    OrderExecutedView {
+        printable: bool,
+        ref_no: u64,
+        qty: u32,
+        match_no: u64,
+    }
+);
+columns!(
+    OrderExecutedWithPriceCols,
+    OrderExecutedWithPriceView {
+        printable: bool,
+        ref_no: u64,
+        qty: u32,
+        price: i32,
+        match_no: u64,
+    }
+);
+columns!(
+    OrderCancelCols,
+    OrderCancelView {
+        reason: CancelReason,
+        ref_no: u64,
+        qty: u32,
+    }
+);
+columns!(
+    OrderDeleteCols,
+    OrderDeleteView {
+        reason: CancelReason,
+        ref_no: u64,
+    }
+);
+columns!(
+    OrderReplaceCols,
+    OrderReplaceView {
+        ref_no: u64,
+        new_ref_no: u64,
+        qty: u32,
+        price: i32,
+    }
+);
+columns!(
+    TradeCols,
+    TradeView {
+        side: Side,
+        ref_no: u64,
+        qty: u32,
+        price: i32,
+        match_no: u64,
+    }
+);
+columns!(
+    CrossTradeCols,
+    CrossTradeView {
+        cross_type: CrossType,
+        qty: u32,
+        price: i32,
+        pclose: i32,
+        open_interest: u32,
+        match_no: u64,
+    }
+);
+
+columns!(
+    ImbalanceCols,
+    ImbalanceView {
+        direction: ImbalanceDirection,
+        paired_qty: u32,
+        imbalance_qty: u32,
+        indicative_price: i32,
+        near_price: i32,
+        far_price: i32,
+        cross_type: CrossType,
+    }
+);
+
+columns!(
+    MarketParticipantCols,
+    MarketParticipantView {
+        state: MarketParticipantState,
+        member_id: u32,
+        primary_maker: bool,
+    }
+);
+
+const TAGS: &[u8; 13] = b"SRHAECXDUPQIL";
+
+// group of each tag byte, NO_GROUP if none
+const NO_GROUP: u8 = 0xff;
+const fn tag_groups() -> [u8; 256] {
+    let mut groups = [NO_GROUP; 256];
+    let mut i = 0;
+    while i < TAGS.len() {
+        groups[TAGS[i] as usize] = i as u8;
+        i += 1;
+    }
+    groups
+}
+static TAG_GROUPS: [u8; 256] = tag_groups();
+
+/// Messages of a batch decoded into columns per message type, buffers
+/// are kept across batches
+#[derive(Debug, Clone, Default)]
+pub struct PitchBatch {
+    pub system_events: SystemEventCols,
+    pub symbol_dirs: SymbolDirectoryCols,
+    pub trading_actions: TradingActionCols,
+    pub add_orders: AddOrderCols,
+    pub executions: OrderExecutedCols,
+    pub executions_with_price: OrderExecutedWithPriceCols,
+    pub cancels: OrderCancelCols,
+    pub deletes: OrderDeleteCols,
+    pub replaces: OrderReplaceCols,
+    pub trades: TradeCols,
+    pub cross_trades: CrossTradeCols,
+    pub imbalances: ImbalanceCols,
+    pub participants: MarketParticipantCols,
+    /// positions of messages of unknown tag or truncated
+    pub invalid: Vec<u32>,
+    // positions grouped by tag, in TAGS order
+    groups: [Vec<u32>; 13],
+}
+
+impl PitchBatch {
+    pub fn new() -> PitchBatch {
+        Default::default()
+    }
+    pub fn clear(&mut self) {
+        self.system_events.clear();
+        self.symbol_dirs.clear();
+        self.trading_actions.clear();
+        self.add_orders.clear();
+        self.executions.clear();
+        self.executions_with_price.clear();
+        self.cancels.clear();
+        self.deletes.clear();
+        self.replaces.clear();
+        self.trades.clear();
+        self.cross_trades.clear();
+        self.imbalances.clear();
+        self.participants.clear();
+        self.invalid.clear();
+    }
+    /// Messages decoded
+    pub fn len(&self) -> usize {
+        self.system_events.len()
+            + self.symbol_dirs.len()
+            + self.trading_actions.len()
+            + self.add_orders.len()
+            + self.executions.len()
+            + self.executions_with_price.len()
+            + self.cancels.len()
+            + self.deletes.len()
+            + self.replaces.len()
+            + self.trades.len()
+            + self.cross_trades.len()
+            + self.imbalances.len()
+            + self.participants.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Decode messages of a series slice, replacing previous batch,
+    /// return messages decoded
+    pub fn decode(&mut self, msgs: &[ClMessage]) -> usize {
+        self.decode_with(msgs.len(), &|i| msgs[i].data())
+    }
+    /// Decode raw messages, e.g. collected from `MoldPacket::messages`
+    pub fn decode_slices(&mut self, msgs: &[&[u8]]) -> usize {
+        self.decode_with(msgs.len(), &|i| msgs[i])
+    }
+    fn decode_with<'a, F: Fn(usize) -> &'a [u8]>(&mut self, n: usize, get: &F) -> usize {
+        self.clear();
+        for g in self.groups.iter_mut() {
+            g.clear();
+        }
+        for i in 0..n {
+            let tag = get(i).first().copied().unwrap_or(0);
+            match TAG_GROUPS[tag as usize] {
+                NO_GROUP => self.invalid.push(i as u32),
+                k => self.groups[k as usize].push(i as u32),
+            }
+        }
+        let (g, inv) = (&self.groups, &mut self.invalid);
+        self.system_events.decode(&g[0], get, inv);
+        self.symbol_dirs.decode(&g[1], get, inv);
+        self.trading_actions.decode(&g[2], get, inv);
+        self.add_orders.decode(&g[3], get, inv);
+        self.executions.decode(&g[4], get, inv);
+        self.executions_with_price.decode(&g[5], get, inv);
+        self.cancels.decode(&g[6], get, inv);
+        self.deletes.decode(&g[7], get, inv);
+        self.replaces.decode(&g[8], get, inv);
+        self.trades.decode(&g[9], get, inv);
+        self.cross_trades.decode(&g[10], get, inv);
+        self.imbalances.decode(&g[11], get, inv);
+        self.participants.decode(&g[12], get, inv);
+        self.invalid.sort_unstable();
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::*;
+    use crate::testutil::cl_message;
+
+    #[test]
+    fn test_batch() {
+        let mut msgs = Vec::new();
+        for i in 0..300u32 {
+            let body = match i % 3 {
+                0 => Body::AddOrder(AddOrder {
+                    reference: i as u64,
+                    side: Side::Sell,
+                    qty: i,
+                    price: -(i as i32),
+                }),
+                1 => Body::Trade(Trade {
+                    reference: i as u64,
+                    side: Side::Buy,
+                    qty: 1,
+                    price: 2,
+                    match_no: i as u64 * 10,
+                }),
+                _ => Body::OrderDelete(OrderDelete {
+                    reason: CancelReason::OddLot,
+                    reference: i as u64,
+                }),
+            };
+            let msg = Message {
+                index: (i % 4) as u16,
+                tracking: i as u16,
+                timestamp: i * 7,
+                body,
+            };
+            msgs.push(cl_message(&msg));
+        }
+        msgs.push(ClMessage::new(b"Zbad"));
+        msgs.push(ClMessage::new(b"Ashort"));
+        let mut batch = PitchBatch::new();
+        assert_eq!(batch.decode(&msgs), 300);
+        assert_eq!(batch.invalid, vec![300, 301]);
+        let ao = &batch.add_orders;
+        assert_eq!(
+            (ao.len(), batch.trades.len(), batch.deletes.len()),
+            (100, 100, 100)
+        );
+        assert_eq!((ao.pos[5], ao.index[5], ao.tracking[5]), (15, 3, 15));
+        assert_eq!((ao.timestamp[5], ao.qty[5], ao.price[5]), (105, 15, -15));
+        assert_eq!(ao.side[5], Side::Sell);
+        assert_eq!(batch.trades.match_no[2], 70);
+        assert_eq!(batch.deletes.reason[0], CancelReason::OddLot);
+        // columns agree with message at a time decoding
+        for (k, p) in batch.trades.pos.iter().enumerate() {
+            match from_bytes(msgs[*p as usize].data()).unwrap().body {
+                Body::Trade(t) => assert_eq!(t.reference, batch.trades.ref_no[k]),
+                _ => panic!("expect Trade"),
+            }
+        }
+        let raw: Vec<&[u8]> = msgs[..10].iter().map(|m| m.data()).collect();
+        assert_eq!(batch.decode_slices(&raw), 10);
+        assert!(batch.invalid.is_empty());
+        assert_eq!(batch.add_orders.len(), 4);
+    }
+}
@@ -0,0 +1,435 @@
+//! book - full depth limit order book maintained from PITCH messages
+//!
+//! Every live order is tracked by its reference number, orders are
+//! queued per price level in time priority, one `Book` per instrument
+//! `index`.
+
+use super::enums::Side;
+use super::pitch::{Body, Message};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+/// A resting order in the book
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Order {
+    pub reference: u64,
+    pub side: Side,
+    pub qty: u32,
+    pub price: i32,
+}
+
+/// Aggregated view of one price level
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price: i32,
+    pub qty: u64,
+    pub orders: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BookError {
+    DuplicateOrder(u64),
+    UnknownOrder(u64),
+    Overfill(u64),
+    UnknownSide(u64),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::DuplicateOrder(r) => write!(f, "duplicate order reference {}", r),
+            BookError::UnknownOrder(r) => write!(f, "unknown order reference {}", r),
+            BookError::Overfill(r) => write!(f, "qty exceeds open qty of order {}", r),
+            BookError::UnknownSide(r) => write!(f, "unknown side of order {}", r),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+// Buy and BuyCover rest on the bid side, Sell and SellClose on the offer
+pub(crate) fn is_bid(side: Side) -> bool {
+    matches!(side, Side::Buy | Side::BuyCover)
+}
+
+#[derive(Default)]
+struct Level {
+    qty: u64,
+    queue: VecDeque<u64>,
+}
+
+impl Level {
+    fn remove(&mut self, reference: u64) {
+        if let Some(pos) = self.queue.iter().position(|r| *r == reference) {
+            self.queue.remove(pos);
+        }
+    }
+}
+
+/// Order book of a single instrument
+#[derive(Default)]
+pub struct Book {
+    index: u16,
+    bids: BTreeMap<i32, Level>,
+    asks: BTreeMap<i32, Level>,
+    orders: HashMap<u64, Order>,
+}
+
+impl Book {
+    pub fn new(index: u16) -> Book {
+        Book {
+            index,
+            ..Default::default()
+        }
+    }
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+    fn side_levels(&self, side: Side) -> &BTreeMap<i32, Level> {
+        if is_bid(side) {
+            &self.bids
+        } else {
+            &self.asks
+        }
+    }
+    fn side_levels_mut(&mut self, side: Side) -> &mut BTreeMap<i32, Level> {
+        if is_bid(side) {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        }
+    }
+    pub fn add(&mut self, o: Order) -> Result<(), BookError> {
+        if self.orders.contains_key(&o.reference) {
+            return Err(BookError::DuplicateOrder(o.reference));
+        }
+        if let Side::Unknown(_) = o.side {
+            return Err(BookError::UnknownSide(o.reference));
+        }
+        let lvl = self.side_levels_mut(o.side).entry(o.price).or_default();
+        lvl.qty += o.qty as u64;
+        lvl.queue.push_back(o.reference);
+        self.orders.insert(o.reference, o);
+        Ok(())
+    }
+    /// Reduce open qty of an order by executed or cancelled qty,
+    /// the order is removed when no qty left
+    pub fn reduce(&mut self, reference: u64, qty: u32) -> Result<Order, BookError> {
+        let o = match self.orders.get_mut(&reference) {
+            Some(o) => o,
+            None => return Err(BookError::UnknownOrder(reference)),
+        };
+        if qty > o.qty {
+            return Err(BookError::Overfill(reference));
+        }
+        o.qty -= qty;
+        let o = *o;
+        let levels = if is_bid(o.side) {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        };
+        if let Some(lvl) = levels.get_mut(&o.price) {
+            lvl.qty -= qty as u64;
+            if o.qty == 0 {
+                lvl.remove(reference);
+                if lvl.queue.is_empty() {
+                    levels.remove(&o.price);
+                }
+            }
+        }
+        if o.qty == 0 {
+            self.orders.remove(&reference);
+        }
+        Ok(o)
+    }
+    /// Remove an order from the book, return the removed order
+    pub fn remove(&mut self, reference: u64) -> Result<Order, BookError> {
+        let o = match self.orders.remove(&reference) {
+            Some(o) => o,
+            None => return Err(BookError::UnknownOrder(reference)),
+        };
+        let levels = self.side_levels_mut(o.side);
+        if let Some(lvl) = levels.get_mut(&o.price) {
+            lvl.qty -= o.qty as u64;
+            lvl.remove(reference);
+            if lvl.queue.is_empty() {
+                levels.remove(&o.price);
+            }
+        }
+        Ok(o)
+    }
+    /// Replace order, new order keeps side but loses time priority
+    pub fn replace(
+        &mut self,
+        old_reference: u64,
+        new_reference: u64,
+        qty: u32,
+        price: i32,
+    ) -> Result<(), BookError> {
+        if old_reference != new_reference && self.orders.contains_key(&new_reference) {
+            return Err(BookError::DuplicateOrder(new_reference));
+        }
+        let o = self.remove(old_reference)?;
+        self.add(Order {
+            reference: new_reference,
+            side: o.side,
+            qty,
+            price,
+        })
+    }
+    pub fn order(&self, reference: u64) -> Option<&Order> {
+        self.orders.get(&reference)
+    }
+    /// Number of live orders
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+    /// Number of price levels on the side
+    pub fn level_count(&self, side: Side) -> usize {
+        self.side_levels(side).len()
+    }
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(p, l)| price_level(*p, l))
+    }
+    pub fn best_offer(&self) -> Option<PriceLevel> {
+        self.asks.iter().next().map(|(p, l)| price_level(*p, l))
+    }
+    /// best bid and best offer
+    pub fn bbo(&self) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        (self.best_bid(), self.best_offer())
+    }
+    pub fn level(&self, side: Side, price: i32) -> Option<PriceLevel> {
+        self.side_levels(side)
+            .get(&price)
+            .map(|l| price_level(price, l))
+    }
+    /// Up to n best price levels of the side, best price first
+    pub fn depth(&self, side: Side, n: usize) -> Vec<PriceLevel> {
+        let mut res = Vec::new();
+        self.depth_into(side, n, &mut res);
+        res
+    }
+    /// As `depth`, into out replacing its content
+    pub fn depth_into(&self, side: Side, n: usize, out: &mut Vec<PriceLevel>) {
+        out.clear();
+        let it = self.side_levels(side).iter();
+        if is_bid(side) {
+            out.extend(it.rev().take(n).map(|(p, l)| price_level(*p, l)));
+        } else {
+            out.extend(it.take(n).map(|(p, l)| price_level(*p, l)));
+        }
+    }
+    /// Orders queued at the price level in time priority
+    pub fn level_orders(&self, side: Side, price: i32) -> impl Iterator<Item = &Order> + '_ {
+        self.side_levels(side)
+            .get(&price)
+            .into_iter()
+            .flat_map(|l| l.queue.iter())
+            .filter_map(move |r| self.orders.get(r))
+    }
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.orders.clear();
+    }
+}
+
+fn price_level(price: i32, l: &Level) -> PriceLevel {
+    PriceLevel {
+        price,
+        qty: l.qty,
+        orders: l.queue.len() as u32,
+    }
+}
+
+/// Order books for all instruments, keyed by PITCH `index`
+#[derive(Default)]
+pub struct OrderBook {
+    books: HashMap<u16, Book>,
+}
+
+impl OrderBook {
+    pub fn new() -> OrderBook {
+        Default::default()
+    }
+    /// Apply one PITCH message to the book of instrument `msg.index`
+    pub fn apply(&mut self, msg: &Message) -> Result<(), BookError> {
+        let index = msg.index;
+        match &msg.body {
+            Body::SymbolDirectory(_) => {
+                self.book_mut(index);
+                Ok(())
+            }
+            Body::AddOrder(s) => self.book_mut(index).add(Order {
+                reference: s.reference,
+                side: s.side,
+                qty: s.qty,
+                price: s.price,
+            }),
+            Body::OrderExecuted(s) => self.reduce(index, s.reference, s.qty),
+            Body::OrderExecutedWithPrice(s) => self.reduce(index, s.reference, s.qty),
+            Body::OrderCancelled(s) => self.reduce(index, s.reference, s.cancelled),
+            Body::OrderDelete(s) => match self.books.get_mut(&index) {
+                Some(b) => b.remove(s.reference).map(|_| ()),
+                None => Err(BookError::UnknownOrder(s.reference)),
+            },
+            Body::ReplaceOrder(s) => match self.books.get_mut(&index) {
+                Some(b) => b.replace(s.old_reference, s.new_reference, s.qty, s.price),
+                None => Err(BookError::UnknownOrder(s.old_reference)),
+            },
+            _ => Ok(()),
+        }
+    }
+    fn reduce(&mut self, index: u16, reference: u64, qty: u32) -> Result<(), BookError> {
+        match self.books.get_mut(&index) {
+            Some(b) => b.reduce(reference, qty).map(|_| ()),
+            None => Err(BookError::UnknownOrder(reference)),
+        }
+    }
+    fn book_mut(&mut self, index: u16) -> &mut Book {
+        self.books.entry(index).or_insert_with(|| Book::new(index))
+    }
+    pub fn book(&self, index: u16) -> Option<&Book> {
+        self.books.get(&index)
+    }
+    pub fn books(&self) -> impl Iterator<Item = &Book> {
+        self.books.values()
+    }
+    /// Number of instruments
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.books.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{
+        AddOrder, CancelReason, OrderCancelled, OrderDelete, OrderExecuted, ReplaceOrder,
+    };
+
+    fn msg(index: u16, body: Body) -> Message {
+        Message {
+            index,
+            tracking: 0,
+            timestamp: 0,
+            body,
+        }
+    }
+
+    fn add(index: u16, reference: u64, side: Side, qty: u32, price: i32) -> Message {
+        msg(
+            index,
+            Body::AddOrder(AddOrder {
+                reference,
+                side,
+                qty,
+                price,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_book_levels() {
+        let mut ob = OrderBook::new();
+        ob.apply(&add(1, 1, Side::Buy, 10, 100)).unwrap();
+        ob.apply(&add(1, 2, Side::Buy, 5, 100)).unwrap();
+        ob.apply(&add(1, 3, Side::BuyCover, 7, 99)).unwrap();
+        ob.apply(&add(1, 4, Side::Sell, 3, 101)).unwrap();
+        ob.apply(&add(1, 5, Side::SellClose, 4, 102)).unwrap();
+        ob.apply(&add(2, 6, Side::Sell, 1, 500)).unwrap();
+        assert_eq!(ob.len(), 2);
+        let b = ob.book(1).unwrap();
+        let (bid, ask) = b.bbo();
+        assert_eq!(
+            bid,
+            Some(PriceLevel {
+                price: 100,
+                qty: 15,
+                orders: 2
+            })
+        );
+        assert_eq!(ask.unwrap().price, 101);
+        let d = b.depth(Side::Buy, 5);
+        assert_eq!(d.len(), 2);
+        assert_eq!(d[1].price, 99);
+        let d = b.depth(Side::Sell, 1);
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].qty, 3);
+        let refs: Vec<u64> = b
+            .level_orders(Side::Buy, 100)
+            .map(|o| o.reference)
+            .collect();
+        assert_eq!(refs, vec![1, 2]);
+        assert_eq!(
+            ob.apply(&add(1, 1, Side::Buy, 1, 98)),
+            Err(BookError::DuplicateOrder(1))
+        );
+    }
+
+    #[test]
+    fn test_book_updates() {
+        let mut ob = OrderBook::new();
+        ob.apply(&add(1, 1, Side::Buy, 10, 100)).unwrap();
+        ob.apply(&add(1, 2, Side::Buy, 5, 100)).unwrap();
+        let exec = msg(
+            1,
+            Body::OrderExecuted(OrderExecuted {
+                printable: true,
+                reference: 1,
+                qty: 4,
+                match_no: 1,
+            }),
+        );
+        ob.apply(&exec).unwrap();
+        assert_eq!(ob.book(1).unwrap().best_bid().unwrap().qty, 11);
+        let cancel = msg(
+            1,
+            Body::OrderCancelled(OrderCancelled {
+                reason: CancelReason::ByUser,
+                reference: 1,
+                cancelled: 6,
+            }),
+        );
+        ob.apply(&cancel).unwrap();
+        let b = ob.book(1).unwrap();
+        assert!(b.order(1).is_none());
+        assert_eq!(b.best_bid().unwrap().orders, 1);
+        let replace = msg(
+            1,
+            Body::ReplaceOrder(ReplaceOrder {
+                old_reference: 2,
+                new_reference: 3,
+                qty: 8,
+                price: 101,
+            }),
+        );
+        ob.apply(&replace).unwrap();
+        let b = ob.book(1).unwrap();
+        assert!(b.level(Side::Buy, 100).is_none());
+        assert_eq!(b.order(3).unwrap().side, Side::Buy);
+        assert_eq!(b.best_bid().unwrap().qty, 8);
+        let del = msg(
+            1,
+            Body::OrderDelete(OrderDelete {
+                reason: CancelReason::ByUser,
+                reference: 3,
+            }),
+        );
+        ob.apply(&del).unwrap();
+        let b = ob.book(1).unwrap();
+        assert_eq!(b.order_count(), 0);
+        assert_eq!(b.level_count(Side::Buy), 0);
+        assert_eq!(ob.apply(&del), Err(BookError::UnknownOrder(3)));
+    }
+}
@@ -0,0 +1,422 @@
+//! builder - encode PITCH messages straight into ClMessage
+//!
+//! The builder stamps each message with time since the hour of its
+//! SysClock and the next tracking number of the instrument. When the hour
+//! rolls over a SystemEvent carrying the new `time_hours` is emitted ahead
+//! of the message, so readers can rebuild absolute time.
+
+use super::enums::EventCode;
+use super::handler::SymbolDirectoryRef;
+use super::pitch::*;
+use super::registry::symbol_len;
+use crate::{ClMessage, SysClock};
+use std::collections::HashMap;
+use std::iter;
+
+/// Message encoded, with hour marker to publish before it if the hour
+/// rolled over
+#[derive(Copy, Clone)]
+pub struct Encoded {
+    marker: Option<ClMessage>,
+    msg: ClMessage,
+}
+
+impl Encoded {
+    pub fn marker(&self) -> Option<&ClMessage> {
+        self.marker.as_ref()
+    }
+    pub fn message(&self) -> &ClMessage {
+        &self.msg
+    }
+    /// Marker if any, then message
+    pub fn iter(&self) -> impl Iterator<Item = &ClMessage> {
+        self.marker.iter().chain(iter::once(&self.msg))
+    }
+}
+
+// little endian field appends
+trait Put {
+    fn put16(&mut self, v: u16);
+    fn put32(&mut self, v: u32);
+    fn put64(&mut self, v: u64);
+}
+
+impl Put for ClMessage {
+    fn put16(&mut self, v: u16) {
+        *self += &v.to_le_bytes()[..];
+    }
+    fn put32(&mut self, v: u32) {
+        *self += &v.to_le_bytes()[..];
+    }
+    fn put64(&mut self, v: u64) {
+        *self += &v.to_le_bytes()[..];
+    }
+}
+
+pub struct PitchBuilder {
+    clock: SysClock,
+    hours: Option<u32>,
+    hour_event: EventCode,
+    tracking: HashMap<u16, u16>,
+}
+
+impl PitchBuilder {
+    pub fn new(clock: SysClock) -> PitchBuilder {
+        PitchBuilder {
+            clock,
+            hours: None,
+            hour_event: EventCode::StartOfSystemHours,
+            tracking: HashMap::new(),
+        }
+    }
+    pub fn clock(&self) -> &SysClock {
+        &self.clock
+    }
+    /// To set time of a simulated clock
+    pub fn clock_mut(&mut self) -> &mut SysClock {
+        &mut self.clock
+    }
+    /// Event code of hour markers, StartOfSystemHours by default
+    pub fn set_hour_event(&mut self, event: EventCode) {
+        self.hour_event = event;
+    }
+    /// Last tracking number assigned to instrument index
+    pub fn tracking(&self, index: u16) -> u16 {
+        self.tracking.get(&index).copied().unwrap_or(0)
+    }
+    fn next_tracking(&mut self, index: u16) -> u16 {
+        let t = self.tracking.entry(index).or_insert(0);
+        *t = t.wrapping_add(1);
+        *t
+    }
+    // SystemEvent is of index 0, sharing its tracking numbers
+    fn sys_event(&mut self, event: EventCode, hours: u32, ts: u32) -> ClMessage {
+        let mut m = ClMessage::new(&[b'S', u8::from(event)]);
+        m.put16(0);
+        m.put16(self.next_tracking(0));
+        m.put32(hours);
+        m.put32(ts);
+        m
+    }
+    // timestamp of now, with hour marker if hour rolled over
+    fn stamp(&mut self) -> (u32, Option<ClMessage>) {
+        let now = self.clock.now();
+        let (hours, ts) = (now.as_hours(), now.subhour_micros());
+        if self.hours == Some(hours) {
+            return (ts, None);
+        }
+        self.hours = Some(hours);
+        (ts, Some(self.sys_event(self.hour_event, hours, ts)))
+    }
+    // tag, code byte, index, tracking and timestamp of most messages
+    fn head(&mut self, tag: u8, code: u8, index: u16) -> (ClMessage, Option<ClMessage>) {
+        let (ts, marker) = self.stamp();
+        let mut m = ClMessage::new(&[tag, code]);
+        m.put16(index);
+        m.put16(self.next_tracking(index));
+        m.put32(ts);
+        (m, marker)
+    }
+    /// SystemEvent of current hour
+    pub fn system_event(&mut self, event: EventCode) -> Encoded {
+        let now = self.clock.now();
+        let hours = now.as_hours();
+        self.hours = Some(hours);
+        let msg = self.sys_event(event, hours, now.subhour_micros());
+        Encoded { marker: None, msg }
+    }
+    pub fn symbol_directory(&mut self, index: u16, s: &SymbolDirectoryRef<'_>) -> Encoded {
+        let (ts, marker) = self.stamp();
+        let mut symbol = [0u8; 16];
+        let ll = symbol_len(s.symbol);
+        symbol[..ll].copy_from_slice(&s.symbol.as_bytes()[..ll]);
+        let mut msg = ClMessage::new(&[b'R', s.market_category]);
+        msg += &symbol[..];
+        msg += &[s.classification, s.precision as u8][..];
+        msg.put16(index);
+        msg.put16(self.next_tracking(index));
+        msg.put32(ts);
+        msg.put32(s.round_lot_size);
+        msg.put32(s.turnover_multi);
+        msg.put32(s.lower_limit as u32);
+        msg.put32(s.upper_limit as u32);
+        Encoded { marker, msg }
+    }
+    pub fn trading_action(&mut self, index: u16, s: &TradingAction) -> Encoded {
+        let (ts, marker) = self.stamp();
+        let mut msg = ClMessage::new(&[b'H', u8::from(s.trading_state)]);
+        msg.put16(s.reason);
+        msg.put16(index);
+        msg.put16(self.next_tracking(index));
+        msg.put32(ts);
+        Encoded { marker, msg }
+    }
+    pub fn add_order(&mut self, index: u16, s: &AddOrder) -> Encoded {
+        let (mut msg, marker) = self.head(b'A', u8::from(s.side), index);
+        msg.put64(s.reference);
+        msg.put32(s.qty);
+        msg.put32(s.price as u32);
+        Encoded { marker, msg }
+    }
+    pub fn order_executed(&mut self, index: u16, s: &OrderExecuted) -> Encoded {
+        let (mut msg, marker) = self.head(b'E', s.printable as u8, index);
+        msg.put64(s.reference);
+        msg.put32(s.qty);
+        msg.put64(s.match_no);
+        Encoded { marker, msg }
+    }
+    pub fn order_executed_with_price(&mut self, index: u16, s: &OrderExecutedWithPrice) -> Encoded {
+        let (mut msg, marker) = self.head(b'C', s.printable as u8, index);
+        msg.put64(s.reference);
+        msg.put32(s.qty);
+        msg.put64(s.match_no);
+        msg.put32(s.price as u32);
+        Encoded { marker, msg }
+    }
+    pub fn order_cancelled(&mut self, index: u16, s: &OrderCancelled) -> Encoded {
+        let (mut msg, marker) = self.head(b'X', u8::from(s.reason), index);
+        msg.put64(s.reference);
+        msg.put32(s.cancelled);
+        Encoded { marker, msg }
+    }
+    pub fn order_delete(&mut self, index: u16, s: &OrderDelete) -> Encoded {
+        let (mut msg, marker) = self.head(b'D', u8::from(s.reason), index);
+        msg.put64(s.reference);
+        Encoded { marker, msg }
+    }
+    pub fn replace_order(&mut self, index: u16, s: &ReplaceOrder) -> Encoded {
+        let (ts, marker) = self.stamp();
+        let mut msg = ClMessage::new(b"U");
+        msg.put16(index);
+        msg.put16(self.next_tracking(index));
+        msg.put32(ts);
+        msg.put64(s.old_reference);
+        msg.put64(s.new_reference);
+        msg.put32(s.qty);
+        msg.put32(s.price as u32);
+        Encoded { marker, msg }
+    }
+    pub fn trade(&mut self, index: u16, s: &Trade) -> Encoded {
+        let (mut msg, marker) = self.head(b'P', u8::from(s.side), index);
+        msg.put64(s.reference);
+        msg.put32(s.qty);
+        msg.put32(s.price as u32);
+        msg.put64(s.match_no);
+        Encoded { marker, msg }
+    }
+    pub fn cross_trade(&mut self, index: u16, s: &CrossTrade) -> Encoded {
+        let (mut msg, marker) = self.head(b'Q', u8::from(s.cross_type), index);
+        msg.put32(s.qty);
+        msg.put32(s.price as u32);
+        msg.put32(s.pclose as u32);
+        msg.put32(s.open_interest);
+        msg.put64(s.match_no);
+        Encoded { marker, msg }
+    }
+    pub fn imbalance(&mut self, index: u16, s: &Imbalance) -> Encoded {
+        let (mut msg, marker) = self.head(b'I', u8::from(s.direction), index);
+        msg.put32(s.paired_qty);
+        msg.put32(s.imbalance_qty);
+        msg.put32(s.indicative_price as u32);
+        msg.put32(s.near_price as u32);
+        msg.put32(s.far_price as u32);
+        msg += u8::from(s.cross_type);
+        Encoded { marker, msg }
+    }
+    pub fn market_participant(&mut self, index: u16, s: &MarketParticipant) -> Encoded {
+        let (mut msg, marker) = self.head(b'L', u8::from(s.state), index);
+        msg.put32(s.member_id);
+        msg += s.primary_maker as u8;
+        Encoded { marker, msg }
+    }
+    /// Encode body of any type for instrument index
+    pub fn encode(&mut self, index: u16, body: &Body) -> Encoded {
+        match body {
+            Body::SystemEvent(s) => self.system_event(s.event),
+            Body::SymbolDirectory(s) => self.symbol_directory(index, &SymbolDirectoryRef::from(s)),
+            Body::TradingAction(s) => self.trading_action(index, s),
+            Body::AddOrder(s) => self.add_order(index, s),
+            Body::OrderExecuted(s) => self.order_executed(index, s),
+            Body::OrderExecutedWithPrice(s) => self.order_executed_with_price(index, s),
+            Body::OrderCancelled(s) => self.order_cancelled(index, s),
+            Body::OrderDelete(s) => self.order_delete(index, s),
+            Body::ReplaceOrder(s) => self.replace_order(index, s),
+            Body::Trade(s) => self.trade(index, s),
+            Body::CrossTrade(s) => self.cross_trade(index, s),
+            Body::Imbalance(s) => self.imbalance(index, s),
+            Body::MarketParticipant(s) => self.market_participant(index, s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{
+        CancelReason, CrossType, ImbalanceDirection, MarketParticipantState, MsgClock, SeqChecker,
+        SeqStatus, Side, SystemEvent, TradingState,
+    };
+    use crate::testutil::HOURS;
+    use crate::TimeVal;
+
+    fn bodies() -> Vec<Body> {
+        vec![
+            Body::SymbolDirectory(SymbolDirectory {
+                symbol: "cu2409".to_owned(),
+                market_category: b'H',
+                classification: b'F',
+                precision: 1,
+                round_lot_size: 5,
+                turnover_multi: 5,
+                lower_limit: 60_000,
+                upper_limit: 80_000,
+            }),
+            Body::TradingAction(TradingAction {
+                trading_state: TradingState::Halted,
+                reason: 3,
+            }),
+            Body::AddOrder(AddOrder {
+                reference: 1,
+                side: Side::Buy,
+                qty: 10,
+                price: -5,
+            }),
+            Body::OrderExecuted(OrderExecuted {
+                printable: true,
+                reference: 1,
+                qty: 2,
+                match_no: 9,
+            }),
+            Body::OrderExecutedWithPrice(OrderExecutedWithPrice {
+                printable: false,
+                reference: 1,
+                qty: 2,
+                price: 7,
+                match_no: 10,
+            }),
+            Body::OrderCancelled(OrderCancelled {
+                reason: CancelReason::OddLot,
+                reference: 1,
+                cancelled: 3,
+            }),
+            Body::OrderDelete(OrderDelete {
+                reason: CancelReason::ByUser,
+                reference: 1,
+            }),
+            Body::ReplaceOrder(ReplaceOrder {
+                old_reference: 1,
+                new_reference: 2,
+                qty: 3,
+                price: 4,
+            }),
+            Body::Trade(Trade {
+                reference: 2,
+                side: Side::Sell,
+                qty: 1,
+                price: 4,
+                match_no: 11,
+            }),
+            Body::CrossTrade(CrossTrade {
+                qty: 100,
+                price: 5,
+                match_no: 12,
+                cross_type: CrossType::Closing,
+                pclose: 6,
+                open_interest: 7,
+            }),
+            Body::Imbalance(Imbalance {
+                paired_qty: 100,
+                imbalance_qty: 20,
+                direction: ImbalanceDirection::Sell,
+                indicative_price: 5,
+                near_price: 6,
+                far_price: -1,
+                cross_type: CrossType::Opening,
+            }),
+            Body::MarketParticipant(MarketParticipant {
+                member_id: 1234,
+                primary_maker: true,
+                state: MarketParticipantState::Suspended,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut clk = SysClock::new(true);
+        clk.set_timeval(&(TimeVal::from_hours(HOURS) + 1_000_000_000));
+        let mut pb = PitchBuilder::new(clk);
+        let mut out = Vec::new();
+        for (i, body) in bodies().iter().enumerate() {
+            let enc = pb.encode(7, body);
+            assert_eq!(enc.marker().is_some(), i == 0);
+            out.extend(enc.iter().copied());
+            // same bytes as to_bytes of decoded message
+            let msg = from_bytes(enc.message().data()).unwrap();
+            assert_eq!(msg.index, 7);
+            assert_eq!(msg.tracking, i as u16 + 1);
+            assert_eq!(&msg.body, body);
+            assert_eq!(to_bytes(&msg).unwrap(), enc.message().data());
+        }
+        assert_eq!(pb.tracking(7), 12);
+        assert_eq!(pb.tracking(8), 0);
+        // hour rolls over
+        pb.clock_mut()
+            .set_timeval(&(TimeVal::from_hours(HOURS + 1) + 5_000_000));
+        let enc = pb.encode(8, &bodies()[2]);
+        let marker = from_bytes(enc.marker().unwrap().data()).unwrap();
+        assert_eq!(
+            marker.body,
+            Body::SystemEvent(SystemEvent {
+                event: EventCode::StartOfSystemHours,
+                time_hours: HOURS + 1,
+            })
+        );
+        assert_eq!(from_bytes(enc.message().data()).unwrap().tracking, 1);
+        out.extend(enc.iter().copied());
+        let mut mc = MsgClock::default();
+        let times: Vec<u64> = out
+            .iter()
+            .map(|m| mc.update_bytes(m.data()).unwrap())
+            .collect();
+        assert!(times.windows(2).all(|w| w[0] <= w[1]));
+        let t0 = HOURS as u64 * 3_600_000_000;
+        assert!(times[0] >= t0 + 1_000_000 && times[0] < t0 + 2_000_000);
+        assert!(*times.last().unwrap() >= t0 + 3_600_005_000);
+    }
+
+    #[test]
+    fn test_builder_index0() {
+        let mut clk = SysClock::new(true);
+        clk.set_timeval(&TimeVal::from_hours(HOURS));
+        let mut pb = PitchBuilder::new(clk);
+        let mut seq = SeqChecker::new();
+        let mut out = Vec::new();
+        out.extend(pb.system_event(EventCode::StartOfMessages).iter().copied());
+        out.extend(pb.encode(0, &bodies()[2]).iter().copied());
+        out.extend(
+            pb.system_event(EventCode::StartOfMarketHours)
+                .iter()
+                .copied(),
+        );
+        out.extend(pb.encode(0, &bodies()[3]).iter().copied());
+        let status: Vec<SeqStatus> = out.iter().flat_map(|m| seq.check_bytes(m.data())).collect();
+        assert_eq!(status[0], SeqStatus::First);
+        assert!(status[1..].iter().all(|s| *s == SeqStatus::InSequence));
+        assert_eq!(status.len(), 4);
+        assert_eq!(pb.tracking(0), 4);
+        // multi-byte char crossing byte 16 is dropped whole
+        let sd = SymbolDirectory {
+            symbol: "abcdefghijklmno\u{4e2d}".to_owned(),
+            ..match &bodies()[0] {
+                Body::SymbolDirectory(s) => s.clone(),
+                _ => unreachable!(),
+            }
+        };
+        let enc = pb.encode(1, &Body::SymbolDirectory(sd));
+        match from_bytes(enc.message().data()).unwrap().body {
+            Body::SymbolDirectory(s) => assert_eq!(s.symbol, "abcdefghijklmno"),
+            _ => panic!("expect SymbolDirectory"),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! clock - absolute time of PITCH messages
+//!
+//! Message timestamps are microseconds within the hour, the hour base is
+//! `time_hours` of the latest SystemEvent. A timestamp falling back by
+//! more than half an hour is taken as the next hour.
+
+use super::pitch::{peek_header, Body, Message};
+use crate::TimeVal;
+
+const HOUR_MICROS: u64 = 3_600_000_000;
+const HALF_HOUR_MICROS: u32 = 1_800_000_000;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MsgClock {
+    hours: u32,
+    last_ts: u32,
+    synced: bool,
+}
+
+impl MsgClock {
+    /// Clock of hour base hours, e.g. from `TimeVal::as_hours`
+    pub fn new(hours: u32) -> MsgClock {
+        MsgClock {
+            hours,
+            last_ts: 0,
+            synced: false,
+        }
+    }
+    /// Clock positioned at micros since epoch
+    pub fn at(micros: u64) -> MsgClock {
+        MsgClock {
+            hours: (micros / HOUR_MICROS) as u32,
+            last_ts: (micros % HOUR_MICROS) as u32,
+            synced: true,
+        }
+    }
+    pub fn hours(&self) -> u32 {
+        self.hours
+    }
+    /// Hour base set from a SystemEvent or `at`
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+    /// Micros since epoch of latest message
+    pub fn micros(&self) -> u64 {
+        self.hours as u64 * HOUR_MICROS + self.last_ts as u64
+    }
+    pub fn timeval(&self) -> TimeVal {
+        TimeVal::from_hours(self.hours) + self.last_ts as u64 * 1000
+    }
+    /// Advance by msg, return its micros since epoch
+    pub fn update(&mut self, msg: &Message) -> u64 {
+        let hours = match &msg.body {
+            Body::SystemEvent(s) => Some(s.time_hours),
+            _ => None,
+        };
+        self.advance(hours, msg.timestamp)
+    }
+    /// Advance by raw message, None if no header could be peeked
+    pub fn update_bytes(&mut self, buf: &[u8]) -> Option<u64> {
+        let (_, _, ts) = peek_header(buf)?;
+        // time_hours of SystemEvent at offset 6
+        let hours = if buf[0] == b'S' {
+            let mut hh = [0u8; 4];
+            hh.copy_from_slice(&buf[6..10]);
+            Some(u32::from_le_bytes(hh))
+        } else {
+            None
+        };
+        Some(self.advance(hours, ts))
+    }
+    fn advance(&mut self, hours: Option<u32>, ts: u32) -> u64 {
+        if let Some(hh) = hours {
+            self.hours = hh;
+            self.synced = true;
+        } else if ts.saturating_add(HALF_HOUR_MICROS) < self.last_ts {
+            self.hours += 1;
+        }
+        self.last_ts = ts;
+        self.micros()
+    }
+}
+
+/// Micros since epoch of tv
+pub fn timeval_micros(tv: &TimeVal) -> u64 {
+    tv.as_secs() * 1_000_000 + (tv.subsec_nanos() / 1000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{to_bytes, AddOrder, EventCode, Side, SystemEvent};
+
+    fn msg(timestamp: u32, body: Body) -> Message {
+        Message {
+            index: 1,
+            tracking: 1,
+            timestamp,
+            body,
+        }
+    }
+
+    #[test]
+    fn test_msg_clock() {
+        let hours = 435_000;
+        let sys = msg(
+            5,
+            Body::SystemEvent(SystemEvent {
+                event: EventCode::StartOfMarketHours,
+                time_hours: hours,
+            }),
+        );
+        let add = |ts| {
+            let body = Body::AddOrder(AddOrder {
+                reference: 1,
+                side: Side::Buy,
+                qty: 1,
+                price: 1,
+            });
+            to_bytes(&msg(ts, body)).unwrap()
+        };
+        let mut clk = MsgClock::default();
+        assert!(!clk.is_synced());
+        let base = hours as u64 * HOUR_MICROS;
+        assert_eq!(clk.update_bytes(&to_bytes(&sys).unwrap()), Some(base + 5));
+        assert!(clk.is_synced());
+        assert_eq!(
+            clk.update_bytes(&add(3_599_000_000)),
+            Some(base + 3_599_000_000)
+        );
+        // hour rolls without SystemEvent
+        assert_eq!(clk.update_bytes(&add(100)), Some(base + HOUR_MICROS + 100));
+        assert_eq!(clk.hours(), hours + 1);
+        assert_eq!(timeval_micros(&clk.timeval()), clk.micros());
+        assert_eq!(MsgClock::at(clk.micros()).micros(), clk.micros());
+        assert_eq!(clk.update(&sys), base + 5);
+        assert!(clk.update_bytes(b"Z").is_none());
+    }
+}
@@ -0,0 +1,411 @@
+//! depth - aggregated level 2 depth built on top of the order book
+//!
+//! `L2Book` keeps full order-by-order books and reports the top N
+//! price levels per side as snapshots or as minimal deltas, deltas
+//! encode into 64 bytes `ClMessage` via the crate serde.
+
+use super::book::{is_bid, BookError, OrderBook, PriceLevel};
+use super::enums::Side;
+use super::pitch::{Body, Message};
+use crate::serde::Result;
+use crate::{from_msg, to_msg, ClMessage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// message tag of encoded depth delta
+pub const DEPTH_DELTA_TAG: u8 = b'd';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaAction {
+    Insert = b'I' as isize,
+    Update = b'U' as isize,
+    Delete = b'D' as isize,
+}
+
+impl fmt::Display for DeltaAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaAction::Insert => write!(f, "Insert"),
+            DeltaAction::Update => write!(f, "Update"),
+            DeltaAction::Delete => write!(f, "Delete"),
+        }
+    }
+}
+
+/// One price level change, `level` is the 0 based position from the best
+/// price. Deltas of a message are to be applied in order: deletes come
+/// first in descending position before removal, then inserts and updates
+/// in ascending position after the change.
+#[derive(Deserialize, Serialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DepthDelta {
+    pub tag: u8,
+    pub action: u8,
+    pub side: u8,
+    pub level: u8,
+    pub index: u16,
+    pub tracking: u16,
+    pub timestamp: u32,
+    pub price: i32,
+    pub qty: u64,
+    pub orders: u32,
+}
+
+impl DepthDelta {
+    /// None for an unknown action code
+    pub fn action(&self) -> Option<DeltaAction> {
+        match self.action {
+            b'I' => Some(DeltaAction::Insert),
+            b'U' => Some(DeltaAction::Update),
+            b'D' => Some(DeltaAction::Delete),
+            _ => None,
+        }
+    }
+    /// `Side::Buy` for bid levels, `Side::Sell` for offer levels
+    pub fn side(&self) -> Side {
+        if self.side == u8::from(Side::Buy) {
+            Side::Buy
+        } else {
+            Side::Sell
+        }
+    }
+    pub fn to_msg(&self) -> Result<ClMessage> {
+        to_msg(self)
+    }
+    pub fn from_msg(msg: &ClMessage) -> Result<DepthDelta> {
+        from_msg(msg)
+    }
+}
+
+impl fmt::Display for DepthDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action() {
+            Some(a) => a.to_string(),
+            None => format!("Unknown({})", self.action),
+        };
+        write!(
+            f,
+            "(index: {}, {} {} level {}: price {} qty {} orders {})",
+            self.index,
+            action,
+            self.side(),
+            self.level,
+            self.price,
+            self.qty,
+            self.orders
+        )
+    }
+}
+
+/// Top N price levels of one instrument, best price first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthSnapshot {
+    pub index: u16,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+impl DepthSnapshot {
+    /// Snapshot as a sequence of Insert deltas, for late joiners
+    pub fn to_deltas(&self, tracking: u16, timestamp: u32) -> Vec<DepthDelta> {
+        let mut res = Vec::with_capacity(self.bids.len() + self.asks.len());
+        for (side, levels) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for (i, l) in levels.iter().enumerate() {
+                res.push(new_delta(
+                    DeltaAction::Insert,
+                    side,
+                    i,
+                    self.index,
+                    tracking,
+                    timestamp,
+                    l,
+                ));
+            }
+        }
+        res
+    }
+}
+
+fn new_delta(
+    action: DeltaAction,
+    side: Side,
+    level: usize,
+    index: u16,
+    tracking: u16,
+    timestamp: u32,
+    l: &PriceLevel,
+) -> DepthDelta {
+    DepthDelta {
+        tag: DEPTH_DELTA_TAG,
+        action: action as u8,
+        side: u8::from(side),
+        level: if level > 255 { 255 } else { level as u8 },
+        index,
+        tracking,
+        timestamp,
+        price: l.price,
+        qty: l.qty,
+        orders: l.orders,
+    }
+}
+
+/// Aggregated depth per instrument, limited to `depth` levels per side
+pub struct L2Book {
+    book: OrderBook,
+    depth: usize,
+    // level buffers reused across messages
+    before_bids: Vec<PriceLevel>,
+    before_asks: Vec<PriceLevel>,
+    after: Vec<PriceLevel>,
+}
+
+impl L2Book {
+    pub fn new(depth: usize) -> L2Book {
+        L2Book {
+            book: OrderBook::new(),
+            depth,
+            before_bids: Vec::new(),
+            before_asks: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn order_book(&self) -> &OrderBook {
+        &self.book
+    }
+    // sides of the levels touched by the message, looked up before apply
+    fn touched_sides(&self, msg: &Message) -> (bool, bool) {
+        let side = match &msg.body {
+            Body::AddOrder(s) => Some(s.side),
+            Body::OrderExecuted(s) => self.order_side(msg.index, s.reference),
+            Body::OrderExecutedWithPrice(s) => self.order_side(msg.index, s.reference),
+            Body::OrderCancelled(s) => self.order_side(msg.index, s.reference),
+            Body::OrderDelete(s) => self.order_side(msg.index, s.reference),
+            Body::ReplaceOrder(s) => self.order_side(msg.index, s.old_reference),
+            _ => None,
+        };
+        match side {
+            Some(side) => (is_bid(side), !is_bid(side)),
+            None => (false, false),
+        }
+    }
+    fn order_side(&self, index: u16, reference: u64) -> Option<Side> {
+        self.book
+            .book(index)
+            .and_then(|b| b.order(reference))
+            .map(|o| o.side)
+    }
+    /// Apply one PITCH message, append resulting level changes to deltas
+    pub fn apply(
+        &mut self,
+        msg: &Message,
+        deltas: &mut Vec<DepthDelta>,
+    ) -> std::result::Result<(), BookError> {
+        let (bid, ask) = self.touched_sides(msg);
+        let (book, n) = (&self.book, self.depth);
+        if bid {
+            fill_levels(book, msg.index, Side::Buy, n, &mut self.before_bids);
+        }
+        if ask {
+            fill_levels(book, msg.index, Side::Sell, n, &mut self.before_asks);
+        }
+        self.book.apply(msg)?;
+        if bid {
+            fill_levels(&self.book, msg.index, Side::Buy, n, &mut self.after);
+            diff_levels(msg, Side::Buy, &self.before_bids, &self.after, deltas);
+        }
+        if ask {
+            fill_levels(&self.book, msg.index, Side::Sell, n, &mut self.after);
+            diff_levels(msg, Side::Sell, &self.before_asks, &self.after, deltas);
+        }
+        Ok(())
+    }
+    /// Top `depth` levels of instrument
+    pub fn snapshot(&self, index: u16) -> Option<DepthSnapshot> {
+        self.snapshot_n(index, self.depth)
+    }
+    pub fn snapshot_n(&self, index: u16, n: usize) -> Option<DepthSnapshot> {
+        self.book.book(index).map(|b| DepthSnapshot {
+            index,
+            bids: b.depth(Side::Buy, n),
+            asks: b.depth(Side::Sell, n),
+        })
+    }
+}
+
+fn fill_levels(book: &OrderBook, index: u16, side: Side, n: usize, out: &mut Vec<PriceLevel>) {
+    match book.book(index) {
+        Some(b) => b.depth_into(side, n, out),
+        None => out.clear(),
+    }
+}
+
+fn diff_levels(
+    msg: &Message,
+    side: Side,
+    before: &[PriceLevel],
+    after: &[PriceLevel],
+    deltas: &mut Vec<DepthDelta>,
+) {
+    let (index, tracking, timestamp) = (msg.index, msg.tracking, msg.timestamp);
+    // descending, so each delete leaves positions of the next valid
+    for (i, l) in before.iter().enumerate().rev() {
+        if !after.iter().any(|a| a.price == l.price) {
+            let d = new_delta(DeltaAction::Delete, side, i, index, tracking, timestamp, l);
+            deltas.push(d);
+        }
+    }
+    for (i, l) in after.iter().enumerate() {
+        let action = match before.iter().find(|b| b.price == l.price) {
+            Some(b) if b == l => continue,
+            Some(_) => DeltaAction::Update,
+            None => DeltaAction::Insert,
+        };
+        deltas.push(new_delta(action, side, i, index, tracking, timestamp, l));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::{AddOrder, CancelReason, OrderDelete, OrderExecuted};
+
+    fn msg(body: Body) -> Message {
+        Message {
+            index: 3,
+            tracking: 1,
+            timestamp: 1000,
+            body,
+        }
+    }
+
+    fn add(reference: u64, side: Side, qty: u32, price: i32) -> Message {
+        msg(Body::AddOrder(AddOrder {
+            reference,
+            side,
+            qty,
+            price,
+        }))
+    }
+
+    #[test]
+    fn test_depth_deltas() {
+        let mut l2 = L2Book::new(2);
+        let mut deltas = Vec::new();
+        l2.apply(&add(1, Side::Buy, 10, 100), &mut deltas).unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].action(), Some(DeltaAction::Insert));
+        assert_eq!(deltas[0].level, 0);
+        deltas.clear();
+        l2.apply(&add(2, Side::Buy, 5, 100), &mut deltas).unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].action(), Some(DeltaAction::Update));
+        assert_eq!(deltas[0].qty, 15);
+        assert_eq!(deltas[0].orders, 2);
+        deltas.clear();
+        l2.apply(&add(3, Side::Buy, 1, 99), &mut deltas).unwrap();
+        l2.apply(&add(4, Side::Sell, 1, 102), &mut deltas).unwrap();
+        deltas.clear();
+        // new best bid pushes 99 out of 2 levels depth
+        l2.apply(&add(5, Side::BuyCover, 2, 101), &mut deltas)
+            .unwrap();
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[0].action(), Some(DeltaAction::Delete));
+        assert_eq!(deltas[0].price, 99);
+        assert_eq!(deltas[0].level, 1);
+        assert_eq!(deltas[1].action(), Some(DeltaAction::Insert));
+        assert_eq!(deltas[1].price, 101);
+        assert_eq!(deltas[1].side(), Side::Buy);
+        deltas.clear();
+        let del = msg(Body::OrderDelete(OrderDelete {
+            reason: CancelReason::ByUser,
+            reference: 5,
+        }));
+        l2.apply(&del, &mut deltas).unwrap();
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[1].price, 99);
+        assert_eq!(deltas[1].action(), Some(DeltaAction::Insert));
+        deltas.clear();
+        let exec = msg(Body::OrderExecuted(OrderExecuted {
+            printable: true,
+            reference: 4,
+            qty: 1,
+            match_no: 1,
+        }));
+        l2.apply(&exec, &mut deltas).unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].action(), Some(DeltaAction::Delete));
+        assert_eq!(deltas[0].side(), Side::Sell);
+        let snap = l2.snapshot(3).unwrap();
+        assert_eq!(snap.bids.len(), 2);
+        assert!(snap.asks.is_empty());
+        assert_eq!(snap.to_deltas(1, 1000).len(), 2);
+    }
+
+    // apply deltas of one side to levels, in emitted order
+    fn apply_deltas(levels: &mut Vec<PriceLevel>, deltas: &[DepthDelta]) {
+        for d in deltas {
+            let l = PriceLevel {
+                price: d.price,
+                qty: d.qty,
+                orders: d.orders,
+            };
+            let pos = d.level as usize;
+            match d.action() {
+                Some(DeltaAction::Insert) => levels.insert(pos, l),
+                Some(DeltaAction::Update) => levels[pos] = l,
+                Some(DeltaAction::Delete) => {
+                    assert_eq!(levels.remove(pos).price, d.price);
+                }
+                None => panic!("unknown action"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_delta_apply_order() {
+        let mut l2 = L2Book::new(5);
+        let mut deltas = Vec::new();
+        for (r, p) in [(1, 100), (2, 99), (3, 98), (4, 97)] {
+            l2.apply(&add(r, Side::Buy, 1, p), &mut deltas).unwrap();
+        }
+        let mut levels = Vec::new();
+        apply_deltas(&mut levels, &deltas);
+        assert_eq!(levels, l2.snapshot(3).unwrap().bids);
+        // levels 1 and 2 gone at once, deletes keep positions valid
+        let lv = |price| PriceLevel {
+            price,
+            qty: 1,
+            orders: 1,
+        };
+        let after = [lv(100), lv(97), lv(96)];
+        deltas.clear();
+        diff_levels(
+            &add(0, Side::Buy, 0, 0),
+            Side::Buy,
+            &levels,
+            &after,
+            &mut deltas,
+        );
+        let pos: Vec<u8> = deltas.iter().map(|d| d.level).collect();
+        assert_eq!(pos, vec![2, 1, 2]);
+        apply_deltas(&mut levels, &deltas);
+        assert_eq!(levels, after);
+    }
+
+    #[test]
+    fn test_delta_msg() {
+        let mut l2 = L2Book::new(5);
+        let mut deltas = Vec::new();
+        l2.apply(&add(1, Side::Sell, 10, 100), &mut deltas).unwrap();
+        let cl = deltas[0].to_msg().unwrap();
+        assert_eq!(cl.data()[0], DEPTH_DELTA_TAG);
+        let d = DepthDelta::from_msg(&cl).unwrap();
+        assert_eq!(d, deltas[0]);
+        println!("depth delta: {}", d);
+        let unknown = DepthDelta { action: b'x', ..d };
+        assert_eq!(unknown.action(), None);
+        println!("depth delta: {}", unknown);
+    }
+}
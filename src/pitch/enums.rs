@@ -2,15 +2,16 @@ use std::fmt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventCode {
-    StartOfMessages = b'O' as isize,
-    StartOfSystemHours = b'S' as isize,
-    StartOfMarketHours = b'Q' as isize,
-    EndOfMarketHours = b'M' as isize,
-    EndOfSystemHours = b'E' as isize,
-    EndOfMessages = b'C' as isize,
-    EmergencyHalt = b'A' as isize,
-    EmergencyQuoteOnly = b'R' as isize,
-    EmergencyResumption = b'B' as isize,
+    StartOfMessages,
+    StartOfSystemHours,
+    StartOfMarketHours,
+    EndOfMarketHours,
+    EndOfSystemHours,
+    EndOfMessages,
+    EmergencyHalt,
+    EmergencyQuoteOnly,
+    EmergencyResumption,
+    Unknown(u8),
 }
 
 impl fmt::Display for EventCode {
@@ -25,12 +26,46 @@ impl fmt::Display for EventCode {
             EventCode::EmergencyHalt => write!(f, "Emergency Halt"),
             EventCode::EmergencyQuoteOnly => write!(f, "Emergency QuoteOnly"),
             EventCode::EmergencyResumption => write!(f, "Emergency Resumption"),
+            EventCode::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
         }
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+impl From<u8> for EventCode {
+    fn from(v: u8) -> EventCode {
+        match v {
+            b'O' => EventCode::StartOfMessages,
+            b'S' => EventCode::StartOfSystemHours,
+            b'Q' => EventCode::StartOfMarketHours,
+            b'M' => EventCode::EndOfMarketHours,
+            b'E' => EventCode::EndOfSystemHours,
+            b'C' => EventCode::EndOfMessages,
+            b'A' => EventCode::EmergencyHalt,
+            b'R' => EventCode::EmergencyQuoteOnly,
+            b'B' => EventCode::EmergencyResumption,
+            _ => EventCode::Unknown(v),
+        }
+    }
+}
+
+impl From<EventCode> for u8 {
+    fn from(v: EventCode) -> u8 {
+        match v {
+            EventCode::StartOfMessages => b'O',
+            EventCode::StartOfSystemHours => b'S',
+            EventCode::StartOfMarketHours => b'Q',
+            EventCode::EndOfMarketHours => b'M',
+            EventCode::EndOfSystemHours => b'E',
+            EventCode::EndOfMessages => b'C',
+            EventCode::EmergencyHalt => b'A',
+            EventCode::EmergencyQuoteOnly => b'R',
+            EventCode::EmergencyResumption => b'B',
+            EventCode::Unknown(c) => c,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MarketCategory {
     Shfe = b'H' as isize,
     Dce = b'D' as isize,
@@ -57,8 +92,22 @@ impl fmt::Display for MarketCategory {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+impl From<u8> for MarketCategory {
+    fn from(v: u8) -> MarketCategory {
+        match v {
+            b'H' => MarketCategory::Shfe,
+            b'D' => MarketCategory::Dce,
+            b'C' => MarketCategory::Czce,
+            b'F' => MarketCategory::Cffex,
+            b'G' => MarketCategory::Gce,
+            b'S' => MarketCategory::Sse,
+            b'Z' => MarketCategory::Szse,
+            _ => MarketCategory::Unavailable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IssueClassification {
     AmericanDepositaryShare,
     Bond,
@@ -74,26 +123,133 @@ pub enum IssueClassification {
     Unit,
     UnitsPerBenifInt,
     Warrant,
+    Unknown(u8),
+}
+
+impl fmt::Display for IssueClassification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueClassification::AmericanDepositaryShare => write!(f, "American Depositary Share"),
+            IssueClassification::Bond => write!(f, "Bond"),
+            IssueClassification::CommonStock => write!(f, "Common Stock"),
+            IssueClassification::Futures => write!(f, "Futures"),
+            IssueClassification::Options => write!(f, "Options"),
+            IssueClassification::DepositoryReceipt => write!(f, "Depository Receipt"),
+            IssueClassification::OrdinaryShare => write!(f, "Ordinary Share"),
+            IssueClassification::PreferredStock => write!(f, "Preferred Stock"),
+            IssueClassification::OtherSecurities => write!(f, "Other Securities"),
+            IssueClassification::Right => write!(f, "Right"),
+            IssueClassification::ConvertibleDebenture => write!(f, "Convertible Debenture"),
+            IssueClassification::Unit => write!(f, "Unit"),
+            IssueClassification::UnitsPerBenifInt => write!(f, "Units/Benif Int"),
+            IssueClassification::Warrant => write!(f, "Warrant"),
+            IssueClassification::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<IssueClassification> for u8 {
+    fn from(v: IssueClassification) -> u8 {
+        match v {
+            IssueClassification::AmericanDepositaryShare => b'A',
+            IssueClassification::Bond => b'B',
+            IssueClassification::CommonStock => b'C',
+            IssueClassification::Futures => b'F',
+            IssueClassification::Options => b'O',
+            IssueClassification::DepositoryReceipt => b'E',
+            IssueClassification::OrdinaryShare => b'S',
+            IssueClassification::PreferredStock => b'P',
+            IssueClassification::OtherSecurities => b'Q',
+            IssueClassification::Right => b'R',
+            IssueClassification::ConvertibleDebenture => b'T',
+            IssueClassification::Unit => b'U',
+            IssueClassification::UnitsPerBenifInt => b'V',
+            IssueClassification::Warrant => b'W',
+            IssueClassification::Unknown(c) => c,
+        }
+    }
+}
+
+impl From<u8> for IssueClassification {
+    fn from(v: u8) -> IssueClassification {
+        match v {
+            b'A' => IssueClassification::AmericanDepositaryShare,
+            b'B' => IssueClassification::Bond,
+            b'C' => IssueClassification::CommonStock,
+            b'F' => IssueClassification::Futures,
+            b'O' => IssueClassification::Options,
+            b'E' => IssueClassification::DepositoryReceipt,
+            b'S' => IssueClassification::OrdinaryShare,
+            b'P' => IssueClassification::PreferredStock,
+            b'Q' => IssueClassification::OtherSecurities,
+            b'R' => IssueClassification::Right,
+            b'T' => IssueClassification::ConvertibleDebenture,
+            b'U' => IssueClassification::Unit,
+            b'V' => IssueClassification::UnitsPerBenifInt,
+            b'W' => IssueClassification::Warrant,
+            _ => IssueClassification::Unknown(v),
+        }
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MarketParticipantState {
     Active,
     Excused,
     Withdrawn,
     Suspended,
     Deleted,
+    Unknown(u8),
+}
+
+impl fmt::Display for MarketParticipantState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketParticipantState::Active => write!(f, "Active"),
+            MarketParticipantState::Excused => write!(f, "Excused"),
+            MarketParticipantState::Withdrawn => write!(f, "Withdrawn"),
+            MarketParticipantState::Suspended => write!(f, "Suspended"),
+            MarketParticipantState::Deleted => write!(f, "Deleted"),
+            MarketParticipantState::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for MarketParticipantState {
+    fn from(v: u8) -> MarketParticipantState {
+        match v {
+            b'A' => MarketParticipantState::Active,
+            b'E' => MarketParticipantState::Excused,
+            b'W' => MarketParticipantState::Withdrawn,
+            b'S' => MarketParticipantState::Suspended,
+            b'D' => MarketParticipantState::Deleted,
+            _ => MarketParticipantState::Unknown(v),
+        }
+    }
+}
+
+impl From<MarketParticipantState> for u8 {
+    fn from(v: MarketParticipantState) -> u8 {
+        match v {
+            MarketParticipantState::Active => b'A',
+            MarketParticipantState::Excused => b'E',
+            MarketParticipantState::Withdrawn => b'W',
+            MarketParticipantState::Suspended => b'S',
+            MarketParticipantState::Deleted => b'D',
+            MarketParticipantState::Unknown(c) => c,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TradingState {
-    Halted = b'H' as isize,
-    PreAuction = b'P' as isize,
-    Auction = b'A' as isize,
-    Paused = b'U' as isize,
-    Trading = b'C' as isize,
-    Break = b'B' as isize,
+    Halted,
+    PreAuction,
+    Auction,
+    Paused,
+    Trading,
+    Break,
+    Unknown(u8),
 }
 
 impl fmt::Display for TradingState {
@@ -105,16 +261,46 @@ impl fmt::Display for TradingState {
             TradingState::Paused => write!(f, "Paused"),
             TradingState::Trading => write!(f, "Trading"),
             TradingState::Break => write!(f, "Break"),
+            TradingState::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for TradingState {
+    fn from(v: u8) -> TradingState {
+        match v {
+            b'H' => TradingState::Halted,
+            b'P' => TradingState::PreAuction,
+            b'A' => TradingState::Auction,
+            b'U' => TradingState::Paused,
+            b'C' => TradingState::Trading,
+            b'B' => TradingState::Break,
+            _ => TradingState::Unknown(v),
+        }
+    }
+}
+
+impl From<TradingState> for u8 {
+    fn from(v: TradingState) -> u8 {
+        match v {
+            TradingState::Halted => b'H',
+            TradingState::PreAuction => b'P',
+            TradingState::Auction => b'A',
+            TradingState::Paused => b'U',
+            TradingState::Trading => b'C',
+            TradingState::Break => b'B',
+            TradingState::Unknown(c) => c,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
-    Buy = b'B' as isize,
-    Sell = b'S' as isize,
-    BuyCover = b'C' as isize,
-    SellClose = b'O' as isize,
+    Buy,
+    Sell,
+    BuyCover,
+    SellClose,
+    Unknown(u8),
 }
 
 impl fmt::Display for Side {
@@ -124,19 +310,45 @@ impl fmt::Display for Side {
             Side::Sell => write!(f, "Sell"),
             Side::BuyCover => write!(f, "BuyCover"),
             Side::SellClose => write!(f, "SellClose"),
+            Side::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for Side {
+    fn from(v: u8) -> Side {
+        match v {
+            b'B' => Side::Buy,
+            b'S' => Side::Sell,
+            b'C' => Side::BuyCover,
+            b'O' => Side::SellClose,
+            _ => Side::Unknown(v),
+        }
+    }
+}
+
+impl From<Side> for u8 {
+    fn from(v: Side) -> u8 {
+        match v {
+            Side::Buy => b'B',
+            Side::Sell => b'S',
+            Side::BuyCover => b'C',
+            Side::SellClose => b'O',
+            Side::Unknown(c) => c,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CancelReason {
-    ByUser = b'U' as isize,
-    Arb = b'A' as isize,
-    ByModifyOrder = b'M' as isize,
-    OddLot = b'O' as isize,
-    OutOfPriceBand = b'B' as isize,
-    BrokenSession = b'S' as isize,
-    OutOfNormalTrading = b'N' as isize,
+    ByUser,
+    Arb,
+    ByModifyOrder,
+    OddLot,
+    OutOfPriceBand,
+    BrokenSession,
+    OutOfNormalTrading,
+    Unknown(u8),
 }
 
 impl fmt::Display for CancelReason {
@@ -149,25 +361,93 @@ impl fmt::Display for CancelReason {
             CancelReason::OutOfPriceBand => write!(f, "Out Of PriceBand"),
             CancelReason::BrokenSession => write!(f, "Broken Session"),
             CancelReason::OutOfNormalTrading => write!(f, "Out Of NormalTrading"),
+            CancelReason::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for CancelReason {
+    fn from(v: u8) -> CancelReason {
+        match v {
+            b'U' => CancelReason::ByUser,
+            b'A' => CancelReason::Arb,
+            b'M' => CancelReason::ByModifyOrder,
+            b'O' => CancelReason::OddLot,
+            b'B' => CancelReason::OutOfPriceBand,
+            b'S' => CancelReason::BrokenSession,
+            b'N' => CancelReason::OutOfNormalTrading,
+            _ => CancelReason::Unknown(v),
+        }
+    }
+}
+
+impl From<CancelReason> for u8 {
+    fn from(v: CancelReason) -> u8 {
+        match v {
+            CancelReason::ByUser => b'U',
+            CancelReason::Arb => b'A',
+            CancelReason::ByModifyOrder => b'M',
+            CancelReason::OddLot => b'O',
+            CancelReason::OutOfPriceBand => b'B',
+            CancelReason::BrokenSession => b'S',
+            CancelReason::OutOfNormalTrading => b'N',
+            CancelReason::Unknown(c) => c,
         }
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImbalanceDirection {
     Buy,
     Sell,
     NoImbalance,
     InsufficientOrders,
+    Unknown(u8),
+}
+
+impl fmt::Display for ImbalanceDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImbalanceDirection::Buy => write!(f, "Buy"),
+            ImbalanceDirection::Sell => write!(f, "Sell"),
+            ImbalanceDirection::NoImbalance => write!(f, "No Imbalance"),
+            ImbalanceDirection::InsufficientOrders => write!(f, "Insufficient Orders"),
+            ImbalanceDirection::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for ImbalanceDirection {
+    fn from(v: u8) -> ImbalanceDirection {
+        match v {
+            b'B' => ImbalanceDirection::Buy,
+            b'S' => ImbalanceDirection::Sell,
+            b'N' => ImbalanceDirection::NoImbalance,
+            b'O' => ImbalanceDirection::InsufficientOrders,
+            _ => ImbalanceDirection::Unknown(v),
+        }
+    }
+}
+
+impl From<ImbalanceDirection> for u8 {
+    fn from(v: ImbalanceDirection) -> u8 {
+        match v {
+            ImbalanceDirection::Buy => b'B',
+            ImbalanceDirection::Sell => b'S',
+            ImbalanceDirection::NoImbalance => b'N',
+            ImbalanceDirection::InsufficientOrders => b'O',
+            ImbalanceDirection::Unknown(c) => c,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CrossType {
-    Opening = b'O' as isize,
-    Closing = b'C' as isize,
-    Halted = b'H' as isize,
-    Intraday = b'I' as isize,
+    Opening,
+    Closing,
+    Halted,
+    Intraday,
+    Unknown(u8),
 }
 
 impl fmt::Display for CrossType {
@@ -177,6 +457,31 @@ impl fmt::Display for CrossType {
             CrossType::Closing => write!(f, "Closing UnCross"),
             CrossType::Halted => write!(f, "UnCross after Halted"),
             CrossType::Intraday => write!(f, "Intraday UnCross"),
+            CrossType::Unknown(c) => write!(f, "Unknown(0x{:02x})", c),
+        }
+    }
+}
+
+impl From<u8> for CrossType {
+    fn from(v: u8) -> CrossType {
+        match v {
+            b'O' => CrossType::Opening,
+            b'C' => CrossType::Closing,
+            b'H' => CrossType::Halted,
+            b'I' => CrossType::Intraday,
+            _ => CrossType::Unknown(v),
+        }
+    }
+}
+
+impl From<CrossType> for u8 {
+    fn from(v: CrossType) -> u8 {
+        match v {
+            CrossType::Opening => b'O',
+            CrossType::Closing => b'C',
+            CrossType::Halted => b'H',
+            CrossType::Intraday => b'I',
+            CrossType::Unknown(c) => c,
         }
     }
 }
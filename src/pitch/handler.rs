@@ -0,0 +1,236 @@
+//! handler - dispatch of decoded PITCH messages to typed callbacks
+//!
+//! Implement the callbacks of interest of `PitchHandler`, the others are
+//! no-op. SymbolDirectory is passed as a view borrowing the symbol from
+//! the raw message, other bodies are plain values decoded on the stack.
+
+use super::enums::IssueClassification;
+use super::pitch::*;
+use super::view::SymbolDirectoryView;
+use crate::serde::{Error, Result};
+
+/// Fields common to all PITCH messages
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PitchHeader {
+    pub index: u16,
+    pub tracking: u16,
+    /// Microseconds since Hours
+    pub timestamp: u32,
+}
+
+impl From<&Message> for PitchHeader {
+    fn from(msg: &Message) -> PitchHeader {
+        PitchHeader {
+            index: msg.index,
+            tracking: msg.tracking,
+            timestamp: msg.timestamp,
+        }
+    }
+}
+
+/// SymbolDirectory with symbol borrowed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SymbolDirectoryRef<'a> {
+    pub symbol: &'a str,
+    pub market_category: u8,
+    pub classification: u8,
+    pub precision: i8,
+    pub round_lot_size: u32,
+    pub turnover_multi: u32,
+    pub lower_limit: i32,
+    pub upper_limit: i32,
+}
+
+impl<'a> From<&'a SymbolDirectory> for SymbolDirectoryRef<'a> {
+    fn from(s: &'a SymbolDirectory) -> SymbolDirectoryRef<'a> {
+        SymbolDirectoryRef {
+            symbol: &s.symbol,
+            market_category: s.market_category,
+            classification: s.classification,
+            precision: s.precision,
+            round_lot_size: s.round_lot_size,
+            turnover_multi: s.turnover_multi,
+            lower_limit: s.lower_limit,
+            upper_limit: s.upper_limit,
+        }
+    }
+}
+
+impl SymbolDirectoryRef<'_> {
+    pub fn issue_classification(&self) -> IssueClassification {
+        IssueClassification::from(self.classification)
+    }
+}
+
+impl From<&SymbolDirectoryRef<'_>> for SymbolDirectory {
+    fn from(s: &SymbolDirectoryRef<'_>) -> SymbolDirectory {
+        SymbolDirectory {
+            symbol: s.symbol.to_owned(),
+            market_category: s.market_category,
+            classification: s.classification,
+            precision: s.precision,
+            round_lot_size: s.round_lot_size,
+            turnover_multi: s.turnover_multi,
+            lower_limit: s.lower_limit,
+            upper_limit: s.upper_limit,
+        }
+    }
+}
+
+#[allow(unused_variables)]
+pub trait PitchHandler {
+    fn on_system_event(&mut self, hdr: &PitchHeader, msg: &SystemEvent) {}
+    fn on_symbol_directory(&mut self, hdr: &PitchHeader, msg: &SymbolDirectoryRef<'_>) {}
+    fn on_trading_action(&mut self, hdr: &PitchHeader, msg: &TradingAction) {}
+    fn on_add_order(&mut self, hdr: &PitchHeader, msg: &AddOrder) {}
+    fn on_order_executed(&mut self, hdr: &PitchHeader, msg: &OrderExecuted) {}
+    fn on_order_executed_with_price(&mut self, hdr: &PitchHeader, msg: &OrderExecutedWithPrice) {}
+    fn on_order_cancelled(&mut self, hdr: &PitchHeader, msg: &OrderCancelled) {}
+    fn on_order_delete(&mut self, hdr: &PitchHeader, msg: &OrderDelete) {}
+    fn on_replace_order(&mut self, hdr: &PitchHeader, msg: &ReplaceOrder) {}
+    fn on_trade(&mut self, hdr: &PitchHeader, msg: &Trade) {}
+    fn on_cross_trade(&mut self, hdr: &PitchHeader, msg: &CrossTrade) {}
+    fn on_imbalance(&mut self, hdr: &PitchHeader, msg: &Imbalance) {}
+    fn on_market_participant(&mut self, hdr: &PitchHeader, msg: &MarketParticipant) {}
+}
+
+/// Decode one raw PITCH message and invoke the callback of its type
+pub fn dispatch<H: PitchHandler + ?Sized>(buf: &[u8], mode: DecodeMode, h: &mut H) -> Result<()> {
+    if buf.first() == Some(&b'R') {
+        // trailing bytes only ignored in lenient mode, as from_bytes_mode
+        if mode == DecodeMode::Strict && buf.len() > SymbolDirectoryView::LEN {
+            return Err(Error::TrailingCharacters);
+        }
+        let view = SymbolDirectoryView::new(buf)?;
+        if let IssueClassification::Unknown(code) = view.issue_classification() {
+            if mode == DecodeMode::Strict {
+                return Err(Error::UnknownCode {
+                    field: "classification",
+                    code,
+                    offset: 18,
+                });
+            }
+        }
+        h.on_symbol_directory(&view.header(), &view.to_ref()?);
+        return Ok(());
+    }
+    // no allocation decoding message types other than SymbolDirectory
+    let msg = from_bytes_mode(buf, mode)?;
+    dispatch_message(&msg, h);
+    Ok(())
+}
+
+/// Invoke the callback of type of a decoded message
+pub fn dispatch_message<H: PitchHandler + ?Sized>(msg: &Message, h: &mut H) {
+    let hdr = PitchHeader::from(msg);
+    match &msg.body {
+        Body::SystemEvent(s) => h.on_system_event(&hdr, s),
+        Body::SymbolDirectory(s) => h.on_symbol_directory(&hdr, &SymbolDirectoryRef::from(s)),
+        Body::TradingAction(s) => h.on_trading_action(&hdr, s),
+        Body::AddOrder(s) => h.on_add_order(&hdr, s),
+        Body::OrderExecuted(s) => h.on_order_executed(&hdr, s),
+        Body::OrderExecutedWithPrice(s) => h.on_order_executed_with_price(&hdr, s),
+        Body::OrderCancelled(s) => h.on_order_cancelled(&hdr, s),
+        Body::OrderDelete(s) => h.on_order_delete(&hdr, s),
+        Body::ReplaceOrder(s) => h.on_replace_order(&hdr, s),
+        Body::Trade(s) => h.on_trade(&hdr, s),
+        Body::CrossTrade(s) => h.on_cross_trade(&hdr, s),
+        Body::Imbalance(s) => h.on_imbalance(&hdr, s),
+        Body::MarketParticipant(s) => h.on_market_participant(&hdr, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::Side;
+
+    #[derive(Default)]
+    struct Counter {
+        adds: u32,
+        trades: u32,
+        qty: u32,
+        symbols: Vec<String>,
+    }
+
+    impl PitchHandler for Counter {
+        fn on_symbol_directory(&mut self, hdr: &PitchHeader, msg: &SymbolDirectoryRef<'_>) {
+            assert_eq!(hdr.index, 2);
+            self.symbols.push(msg.symbol.to_owned());
+        }
+        fn on_add_order(&mut self, _hdr: &PitchHeader, msg: &AddOrder) {
+            self.adds += 1;
+            self.qty += msg.qty;
+        }
+        fn on_trade(&mut self, _hdr: &PitchHeader, _msg: &Trade) {
+            self.trades += 1;
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let sd: Vec<u8> = vec![
+            b'R', 78, 99, 117, 49, 57, 48, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 70, 0, 2, 0, 3, 0, 98,
+            116, 140, 58, 5, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let add = Message {
+            index: 1,
+            tracking: 2,
+            timestamp: 3,
+            body: Body::AddOrder(AddOrder {
+                reference: 4,
+                side: Side::Buy,
+                qty: 100,
+                price: 51050,
+            }),
+        };
+        let trade = Message {
+            body: Body::Trade(Trade {
+                reference: 4,
+                side: Side::Sell,
+                qty: 10,
+                price: 51050,
+                match_no: 5,
+            }),
+            ..add.clone()
+        };
+        let mut h = Counter::default();
+        dispatch(&sd, DecodeMode::Strict, &mut h).unwrap();
+        for msg in [&add, &trade, &add] {
+            dispatch(&to_bytes(msg).unwrap(), DecodeMode::Strict, &mut h).unwrap();
+        }
+        assert_eq!((h.adds, h.trades, h.qty), (2, 1, 200));
+        assert_eq!(h.symbols, vec!["cu1908".to_owned()]);
+        // view agrees with owned decoding
+        let view = SymbolDirectoryView::new(&sd).unwrap();
+        let msg = from_bytes(&sd).unwrap();
+        assert_eq!(view.header(), PitchHeader::from(&msg));
+        assert_eq!(
+            Body::SymbolDirectory(SymbolDirectory::from(&view.to_ref().unwrap())),
+            msg.body
+        );
+        dispatch_message(&msg, &mut h);
+        assert_eq!(h.symbols.len(), 2);
+        assert!(dispatch(&sd[..30], DecodeMode::Strict, &mut h).is_err());
+        let mut longer = sd.clone();
+        longer.push(0);
+        assert!(matches!(
+            dispatch(&longer, DecodeMode::Strict, &mut h),
+            Err(Error::TrailingCharacters)
+        ));
+        dispatch(&longer, DecodeMode::Lenient, &mut h).unwrap();
+        assert_eq!(h.symbols.len(), 3);
+        let mut unknown = sd.clone();
+        unknown[18] = b'Z';
+        assert!(matches!(
+            dispatch(&unknown, DecodeMode::Strict, &mut h),
+            Err(Error::UnknownCode {
+                field: "classification",
+                offset: 18,
+                ..
+            })
+        ));
+        dispatch(&unknown, DecodeMode::Lenient, &mut h).unwrap();
+        assert_eq!(h.symbols.len(), 4);
+    }
+}
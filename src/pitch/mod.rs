@@ -7,9 +7,39 @@
 //!
 //! The protocol specification can be found on the [SHFE website](http://www.shfe.comcn/PITCHSpecification.pdf)
 
+mod bars;
+mod batch;
+mod book;
+mod builder;
+mod clock;
+mod depth;
 mod enums;
+mod handler;
 mod pitch;
 mod proto;
+mod registry;
+mod sequence;
+mod session;
+mod view;
 
+pub use bars::{Bar, BarBuilder, BarInterval, BarPolicy, BAR_TAG};
+pub use batch::{
+    AddOrderCols, CrossTradeCols, ImbalanceCols, MarketParticipantCols, OrderCancelCols,
+    OrderDeleteCols, OrderExecutedCols, OrderExecutedWithPriceCols, OrderReplaceCols, PitchBatch,
+    SymbolDirectoryCols, SystemEventCols, TradeCols, TradingActionCols,
+};
+pub use book::{Book, BookError, Order, OrderBook, PriceLevel};
+pub use builder::{Encoded, PitchBuilder};
+pub use clock::{timeval_micros, MsgClock};
+pub use depth::{DeltaAction, DepthDelta, DepthSnapshot, L2Book, DEPTH_DELTA_TAG};
 pub use enums::*;
+pub use handler::{dispatch, dispatch_message, PitchHandler, PitchHeader, SymbolDirectoryRef};
 pub use pitch::*;
+pub use registry::{Instrument, SymbolRegistry};
+pub use sequence::{Gap, SeqChecker, SeqStats, SeqStatus};
+pub use session::{is_legal_transition, ActionReason, InstrumentState, StateEvent, StateTracker};
+pub use view::{
+    AddOrderView, CrossTradeView, ImbalanceView, MarketParticipantView, MessageView,
+    OrderCancelView, OrderDeleteView, OrderExecutedView, OrderExecutedWithPriceView,
+    OrderReplaceView, SymbolDirectoryView, SystemEventView, TradeView, TradingActionView,
+};
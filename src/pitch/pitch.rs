@@ -10,7 +10,9 @@
 use super::super::serde::{Error, Result};
 use super::enums::*;
 use super::proto::*;
+use crate::serde::from_bytes_prefix as de_from_bytes_prefix;
 use crate::{from_bytes as de_from_bytes, to_bytes as ser_to_bytes};
+use serde::Deserialize;
 use std::fmt;
 
 /// An PITCH protocol message. Refer to the protocol spec for interpretation.
@@ -28,57 +30,165 @@ pub struct Message {
     pub body: Body,
 }
 
+/// Decoding policy for code bytes not defined by the protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// unknown enum codes are reported as `Error::UnknownCode`
+    Strict,
+    /// unknown enum codes are carried as `Unknown(u8)` variants, trailing
+    /// bytes appended by newer protocol revisions are ignored
+    Lenient,
+}
+
+/// Decode one PITCH message in strict mode
 pub fn from_bytes(buf: &[u8]) -> Result<Message> {
+    from_bytes_mode(buf, DecodeMode::Strict)
+}
+
+pub fn from_bytes_mode(buf: &[u8], mode: DecodeMode) -> Result<Message> {
     if buf.len() < 8 {
         return Err(Error::Eof);
     }
-    match buf[0] {
+    let msg = match buf[0] {
         b'S' => {
-            let r: SystemEventNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: SystemEventNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'R' => {
-            let r: &SymbolDirectoryNet = SymbolDirectoryNet::from_bytes(buf)?;
-            Ok(Message::from(*r))
+            let r = SymbolDirectoryNet::read(buf)?;
+            Message::from(r)
         }
         b'H' => {
-            let r: SymbolTradingActionNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: SymbolTradingActionNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'A' => {
-            let r: AddOrderNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: AddOrderNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'E' => {
-            let r: OrderExecutedNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: OrderExecutedNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'C' => {
-            let r: OrderExecutedWithPriceNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: OrderExecutedWithPriceNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'X' => {
-            let r: OrderCancelNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: OrderCancelNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'D' => {
-            let r: OrderDeleteNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: OrderDeleteNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'U' => {
-            let r: OrderReplaceNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: OrderReplaceNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'P' => {
-            let r: TradeNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: TradeNet = decode(buf, mode)?;
+            Message::from(r)
         }
         b'Q' => {
-            let r: CrossTradeNet = de_from_bytes(buf)?;
-            Ok(Message::from(r))
+            let r: CrossTradeNet = decode(buf, mode)?;
+            Message::from(r)
+        }
+        b'I' => {
+            let r: NoiiNet = decode(buf, mode)?;
+            Message::from(r)
+        }
+        b'L' => {
+            let r: MarketParticipantNet = decode(buf, mode)?;
+            Message::from(r)
+        }
+        tag => {
+            return Err(Error::UnknownCode {
+                field: "tag",
+                code: tag,
+                offset: 0,
+            })
+        }
+    };
+    if mode == DecodeMode::Strict {
+        if let Some((field, code, offset)) = unknown_code(&msg.body) {
+            return Err(Error::UnknownCode {
+                field,
+                code,
+                offset,
+            });
         }
-        _ => Err(Error::Syntax),
     }
+    Ok(msg)
+}
+
+fn decode<'a, T: Deserialize<'a>>(buf: &'a [u8], mode: DecodeMode) -> Result<T> {
+    match mode {
+        DecodeMode::Strict => de_from_bytes(buf),
+        DecodeMode::Lenient => de_from_bytes_prefix(buf),
+    }
+}
+
+// field name, code and its offset in message
+fn unknown_code(body: &Body) -> Option<(&'static str, u8, usize)> {
+    match body {
+        Body::SystemEvent(s) => match s.event {
+            EventCode::Unknown(c) => Some(("event_code", c, 1)),
+            _ => None,
+        },
+        Body::SymbolDirectory(s) => match s.issue_classification() {
+            IssueClassification::Unknown(c) => Some(("classification", c, 18)),
+            _ => None,
+        },
+        Body::TradingAction(s) => match s.trading_state {
+            TradingState::Unknown(c) => Some(("trading_state", c, 1)),
+            _ => None,
+        },
+        Body::AddOrder(AddOrder { side, .. }) | Body::Trade(Trade { side, .. }) => match side {
+            Side::Unknown(c) => Some(("buy_sell", *c, 1)),
+            _ => None,
+        },
+        Body::OrderCancelled(OrderCancelled { reason, .. })
+        | Body::OrderDelete(OrderDelete { reason, .. }) => match reason {
+            CancelReason::Unknown(c) => Some(("cancel_reason", *c, 1)),
+            _ => None,
+        },
+        Body::CrossTrade(s) => match s.cross_type {
+            CrossType::Unknown(c) => Some(("cross_type", c, 1)),
+            _ => None,
+        },
+        Body::Imbalance(s) => match (s.direction, s.cross_type) {
+            (ImbalanceDirection::Unknown(c), _) => Some(("imbalance_direction", c, 1)),
+            (_, CrossType::Unknown(c)) => Some(("cross_type", c, 30)),
+            _ => None,
+        },
+        Body::MarketParticipant(s) => match s.state {
+            MarketParticipantState::Unknown(c) => Some(("participant_state", c, 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Peek instrument index, tracking and timestamp of a raw message
+/// without decoding the whole message
+pub fn peek_header(buf: &[u8]) -> Option<(u16, u16, u32)> {
+    let (idx_off, ts_off) = match *buf.first()? {
+        b'S' => (2, 10),
+        b'R' => (20, 24),
+        b'U' => (1, 5),
+        b'H' => (4, 8),
+        b'A' | b'E' | b'C' | b'X' | b'D' | b'P' | b'Q' | b'I' | b'L' => (2, 6),
+        _ => return None,
+    };
+    if buf.len() < ts_off + 4 {
+        return None;
+    }
+    let index = u16::from_le_bytes([buf[idx_off], buf[idx_off + 1]]);
+    let tracking = u16::from_le_bytes([buf[idx_off + 2], buf[idx_off + 3]]);
+    let mut ts = [0u8; 4];
+    ts.copy_from_slice(&buf[ts_off..ts_off + 4]);
+    Some((index, tracking, u32::from_le_bytes(ts)))
 }
 
 pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
@@ -86,7 +196,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
     match &v.body {
         Body::SystemEvent(s) => {
             let tag = b'S';
-            let (event_code, time_hours) = (s.event as u8, s.time_hours);
+            let (event_code, time_hours) = (u8::from(s.event), s.time_hours);
             let src = SystemEventNet {
                 tag,
                 event_code,
@@ -142,7 +252,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         }
         Body::TradingAction(s) => {
             let tag = b'H';
-            let (trading_state, reason) = (s.trading_state as u8, s.reason);
+            let (trading_state, reason) = (u8::from(s.trading_state), s.reason);
             let src = SymbolTradingActionNet {
                 tag,
                 index,
@@ -155,7 +265,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         }
         Body::AddOrder(s) => {
             let tag = b'A';
-            let (buy_sell, ref_no) = (s.side as u8, s.reference);
+            let (buy_sell, ref_no) = (u8::from(s.side), s.reference);
             let (qty, price) = (s.qty, s.price);
             let src = AddOrderNet {
                 tag,
@@ -204,7 +314,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         }
         Body::OrderCancelled(s) => {
             let tag = b'X';
-            let cancel_reason = s.reason as u8;
+            let cancel_reason = u8::from(s.reason);
             let (ref_no, qty) = (s.reference, s.cancelled);
             let src = OrderCancelNet {
                 tag,
@@ -219,7 +329,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         }
         Body::OrderDelete(s) => {
             let tag = b'D';
-            let (cancel_reason, ref_no) = (s.reason as u8, s.reference);
+            let (cancel_reason, ref_no) = (u8::from(s.reason), s.reference);
             let src = OrderDeleteNet {
                 tag,
                 index,
@@ -249,7 +359,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         Body::Trade(s) => {
             let tag = b'P';
             let (buy_sell, ref_no, qty, price, match_no) =
-                (s.side as u8, s.reference, s.qty, s.price, s.match_no);
+                (u8::from(s.side), s.reference, s.qty, s.price, s.match_no);
             let src = TradeNet {
                 tag,
                 index,
@@ -266,7 +376,7 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
         Body::CrossTrade(s) => {
             let tag = b'Q';
             let (qty, price) = (s.qty, s.price);
-            let (match_no, type_) = (s.match_no, s.cross_type as u8);
+            let (match_no, type_) = (s.match_no, u8::from(s.cross_type));
             let (pclose, open_interest) = (s.pclose, s.open_interest);
             let src = CrossTradeNet {
                 tag,
@@ -282,6 +392,42 @@ pub fn to_bytes(v: &Message) -> Result<Vec<u8>> {
             };
             ser_to_bytes(&src)
         }
+        Body::Imbalance(s) => {
+            let tag = b'I';
+            let (direction, type_) = (u8::from(s.direction), u8::from(s.cross_type));
+            let (paired_qty, imbalance_qty) = (s.paired_qty, s.imbalance_qty);
+            let (indicative_price, near_price, far_price) =
+                (s.indicative_price, s.near_price, s.far_price);
+            let src = NoiiNet {
+                tag,
+                direction,
+                index,
+                tracking,
+                timestamp,
+                paired_qty,
+                imbalance_qty,
+                indicative_price,
+                near_price,
+                far_price,
+                type_,
+            };
+            ser_to_bytes(&src)
+        }
+        Body::MarketParticipant(s) => {
+            let tag = b'L';
+            let (state, member_id, primary_maker) =
+                (u8::from(s.state), s.member_id, s.primary_maker);
+            let src = MarketParticipantNet {
+                tag,
+                state,
+                index,
+                tracking,
+                timestamp,
+                member_id,
+                primary_maker,
+            };
+            ser_to_bytes(&src)
+        }
     }
 }
 
@@ -319,6 +465,12 @@ pub struct SymbolDirectory {
     pub upper_limit: i32,
 }
 
+impl SymbolDirectory {
+    pub fn issue_classification(&self) -> IssueClassification {
+        IssueClassification::from(self.classification)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TradingAction {
     pub trading_state: TradingState,
@@ -614,6 +766,74 @@ impl From<CrossTradeNet> for Message {
     }
 }
 
+/// Market participant position of a member in an instrument
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketParticipant {
+    /// exchange member number of broker
+    pub member_id: u32,
+    pub primary_maker: bool,
+    pub state: MarketParticipantState,
+}
+
+impl From<MarketParticipantNet> for Message {
+    fn from(s: MarketParticipantNet) -> Message {
+        let (index, tracking, timestamp) = (s.index, s.tracking, s.timestamp);
+        let (member_id, primary_maker, state) = (s.member_id, s.primary_maker, s.state());
+        let body = Body::MarketParticipant(MarketParticipant {
+            member_id,
+            primary_maker,
+            state,
+        });
+        Message {
+            index,
+            tracking,
+            timestamp,
+            body,
+        }
+    }
+}
+
+/// Net Order Imbalance Indicator of a call auction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imbalance {
+    /// qty matched at indicative price
+    pub paired_qty: u32,
+    /// qty left unmatched at indicative price
+    pub imbalance_qty: u32,
+    pub direction: ImbalanceDirection,
+    pub indicative_price: i32,
+    /// price of cross of orders in auction only
+    pub near_price: i32,
+    /// price of cross of auction and continuous orders
+    pub far_price: i32,
+    pub cross_type: CrossType,
+}
+
+impl From<NoiiNet> for Message {
+    fn from(s: NoiiNet) -> Message {
+        let (index, tracking, timestamp) = (s.index, s.tracking, s.timestamp);
+        let (paired_qty, imbalance_qty) = (s.paired_qty, s.imbalance_qty);
+        let (indicative_price, near_price, far_price) =
+            (s.indicative_price, s.near_price, s.far_price);
+        let (direction, cross_type) = (s.direction(), s.cross_type());
+        let body = Body::Imbalance(Imbalance {
+            paired_qty,
+            imbalance_qty,
+            direction,
+            indicative_price,
+            near_price,
+            far_price,
+            cross_type,
+        });
+        Message {
+            index,
+            tracking,
+            timestamp,
+            body,
+        }
+    }
+}
+
 /// The message body. Refer to the protocol spec for interpretation.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Body {
@@ -628,6 +848,8 @@ pub enum Body {
     ReplaceOrder(ReplaceOrder),
     Trade(Trade),
     CrossTrade(CrossTrade),
+    Imbalance(Imbalance),
+    MarketParticipant(MarketParticipant),
 }
 
 #[cfg(test)]
@@ -680,4 +902,235 @@ mod tests {
         ];
         let _msg: Message = from_bytes(&buf[..]).unwrap();
     }
+
+    #[test]
+    fn test_peek_header() {
+        let buf: Vec<u8> = vec![
+            b'A', b'B', 1, 0, 2, 0, 123, 202, 91, 7, 238, 151, 122, 20, 47, 0, 0, 0, 100, 0, 0, 0,
+            106, 199, 0, 0,
+        ];
+        assert_eq!(peek_header(&buf[..]), Some((1, 2, 123456123)));
+        let buf: Vec<u8> = vec![
+            b'R', 78, 99, 117, 49, 57, 48, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 70, 0, 2, 0, 3, 0, 98,
+            116, 140, 58, 5, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let msg = from_bytes(&buf[..]).unwrap();
+        assert_eq!(
+            peek_header(&buf[..]),
+            Some((msg.index, msg.tracking, msg.timestamp))
+        );
+        let msg = Message {
+            index: 7,
+            tracking: 9,
+            timestamp: 11,
+            body: Body::ReplaceOrder(ReplaceOrder {
+                old_reference: 1,
+                new_reference: 2,
+                qty: 3,
+                price: 4,
+            }),
+        };
+        assert_eq!(peek_header(&to_bytes(&msg).unwrap()), Some((7, 9, 11)));
+        // index after the reason of TradingAction
+        let msg = Message {
+            index: 5,
+            tracking: 6,
+            timestamp: 123456,
+            body: Body::TradingAction(TradingAction {
+                trading_state: TradingState::Halted,
+                reason: 0x0102,
+            }),
+        };
+        assert_eq!(peek_header(&to_bytes(&msg).unwrap()), Some((5, 6, 123456)));
+        assert_eq!(peek_header(&buf[..8]), None);
+    }
+
+    #[test]
+    fn test_decode_mode() {
+        let mut buf: Vec<u8> = vec![
+            b'A', b'Z', 1, 0, 2, 0, 123, 202, 91, 7, 238, 151, 122, 20, 47, 0, 0, 0, 100, 0, 0, 0,
+            106, 199, 0, 0,
+        ];
+        match from_bytes(&buf[..]) {
+            Err(Error::UnknownCode {
+                field,
+                code,
+                offset,
+            }) => {
+                assert_eq!(field, "buy_sell");
+                assert_eq!(code, b'Z');
+                assert_eq!(offset, 1);
+            }
+            _ => panic!("expect UnknownCode error"),
+        }
+        let msg = from_bytes_mode(&buf[..], DecodeMode::Lenient).unwrap();
+        match &msg.body {
+            Body::AddOrder(s) => assert_eq!(s.side, Side::Unknown(b'Z')),
+            _ => panic!("expect AddOrder"),
+        }
+        assert_eq!(to_bytes(&msg).unwrap(), buf);
+        // newer revision appends fields
+        buf.extend_from_slice(&[1, 2, 3]);
+        assert!(from_bytes(&buf[..]).is_err());
+        assert!(from_bytes_mode(&buf[..], DecodeMode::Lenient).is_ok());
+        buf[0] = b'z';
+        let err = from_bytes_mode(&buf[..], DecodeMode::Lenient).unwrap_err();
+        println!("decode error: {}", err);
+        assert!(matches!(err, Error::UnknownCode { field: "tag", .. }));
+        let msg = Message {
+            index: 1,
+            tracking: 2,
+            timestamp: 3,
+            body: Body::CrossTrade(CrossTrade {
+                qty: 1,
+                price: 2,
+                match_no: 3,
+                cross_type: CrossType::Unknown(b'Z'),
+                pclose: 4,
+                open_interest: 5,
+            }),
+        };
+        let buf = to_bytes(&msg).unwrap();
+        assert!(matches!(
+            from_bytes(&buf),
+            Err(Error::UnknownCode {
+                field: "cross_type",
+                code: b'Z',
+                offset: 1,
+            })
+        ));
+        assert_eq!(from_bytes_mode(&buf, DecodeMode::Lenient).unwrap(), msg);
+        let msg = Message {
+            index: 1,
+            tracking: 2,
+            timestamp: 3,
+            body: Body::Imbalance(Imbalance {
+                paired_qty: 1,
+                imbalance_qty: 2,
+                direction: ImbalanceDirection::Buy,
+                indicative_price: 3,
+                near_price: 4,
+                far_price: 5,
+                cross_type: CrossType::Unknown(b'Z'),
+            }),
+        };
+        let buf = to_bytes(&msg).unwrap();
+        assert_eq!(buf[30], b'Z');
+        assert!(matches!(
+            from_bytes(&buf),
+            Err(Error::UnknownCode {
+                field: "cross_type",
+                code: b'Z',
+                offset: 30,
+            })
+        ));
+        assert_eq!(from_bytes_mode(&buf, DecodeMode::Lenient).unwrap(), msg);
+        let msg = Message {
+            index: 1,
+            tracking: 2,
+            timestamp: 3,
+            body: Body::SymbolDirectory(SymbolDirectory {
+                symbol: "cu1908".to_owned(),
+                market_category: b'H',
+                classification: b'Z',
+                precision: 0,
+                round_lot_size: 1,
+                turnover_multi: 5,
+                lower_limit: 1,
+                upper_limit: 2,
+            }),
+        };
+        let buf = to_bytes(&msg).unwrap();
+        assert_eq!(buf[18], b'Z');
+        assert!(matches!(
+            from_bytes(&buf),
+            Err(Error::UnknownCode {
+                field: "classification",
+                code: b'Z',
+                offset: 18,
+            })
+        ));
+        let msg = from_bytes_mode(&buf, DecodeMode::Lenient).unwrap();
+        let cls = match &msg.body {
+            Body::SymbolDirectory(s) => s.issue_classification(),
+            _ => panic!("expect SymbolDirectory"),
+        };
+        assert_eq!(cls, IssueClassification::Unknown(b'Z'));
+        assert_eq!(u8::from(cls), b'Z');
+        assert_eq!(
+            IssueClassification::from(b'Q'),
+            IssueClassification::OtherSecurities
+        );
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let msg = Message {
+            index: 3,
+            tracking: 4,
+            timestamp: 5,
+            body: Body::Imbalance(Imbalance {
+                paired_qty: 1000,
+                imbalance_qty: 200,
+                direction: ImbalanceDirection::Buy,
+                indicative_price: 51050,
+                near_price: 51040,
+                far_price: 51060,
+                cross_type: CrossType::Opening,
+            }),
+        };
+        let mut buf = to_bytes(&msg).unwrap();
+        assert_eq!(buf.len(), 31);
+        assert_eq!(from_bytes(&buf).unwrap(), msg);
+        assert_eq!(peek_header(&buf), Some((3, 4, 5)));
+        buf[1] = b'z';
+        assert!(matches!(
+            from_bytes(&buf),
+            Err(Error::UnknownCode {
+                field: "imbalance_direction",
+                ..
+            })
+        ));
+        let msg = from_bytes_mode(&buf, DecodeMode::Lenient).unwrap();
+        assert!(matches!(
+            msg.body,
+            Body::Imbalance(Imbalance {
+                direction: ImbalanceDirection::Unknown(b'z'),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_market_participant() {
+        let msg = Message {
+            index: 3,
+            tracking: 4,
+            timestamp: 5,
+            body: Body::MarketParticipant(MarketParticipant {
+                member_id: 80,
+                primary_maker: true,
+                state: MarketParticipantState::Withdrawn,
+            }),
+        };
+        let mut buf = to_bytes(&msg).unwrap();
+        assert_eq!(buf.len(), 15);
+        assert_eq!(from_bytes(&buf).unwrap(), msg);
+        assert_eq!(MarketParticipantState::Withdrawn.to_string(), "Withdrawn");
+        buf[1] = b'?';
+        assert!(from_bytes(&buf).is_err());
+        let buf: Vec<u8> = vec![
+            b'R', 78, 99, 117, 49, 57, 48, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 70, 0, 2, 0, 3, 0, 98,
+            116, 140, 58, 5, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        match from_bytes(&buf).unwrap().body {
+            Body::SymbolDirectory(s) => {
+                let cls = s.issue_classification();
+                assert_eq!(cls, IssueClassification::Futures);
+                assert_eq!(u8::from(cls), s.classification);
+                assert_eq!(cls.to_string(), "Futures");
+            }
+            _ => panic!("expect SymbolDirectory"),
+        }
+    }
 }
@@ -1,4 +1,7 @@
-use super::enums::{CancelReason, CrossType, EventCode, Side, TradingState};
+use super::enums::{
+    CancelReason, CrossType, EventCode, ImbalanceDirection, MarketParticipantState, Side,
+    TradingState,
+};
 use crate::serde::{Error, Result};
 use serde::{Deserialize, Serialize};
 
@@ -14,18 +17,7 @@ pub struct SystemEventNet {
 
 impl SystemEventNet {
     pub fn event(&self) -> EventCode {
-        match self.event_code {
-            b'O' => EventCode::StartOfMessages,
-            b'S' => EventCode::StartOfSystemHours,
-            b'Q' => EventCode::StartOfMarketHours,
-            b'M' => EventCode::EndOfMarketHours,
-            b'E' => EventCode::EndOfSystemHours,
-            b'C' => EventCode::EndOfMessages,
-            b'A' => EventCode::EmergencyHalt,
-            b'R' => EventCode::EmergencyQuoteOnly,
-            b'B' => EventCode::EmergencyResumption,
-            _ => todo!(),
-        }
+        EventCode::from(self.event_code)
     }
 }
 
@@ -64,18 +56,30 @@ pub struct SymbolDirectoryNet {
 }
 
 impl SymbolDirectoryNet {
+    /// Reference into buf, which must be aligned for SymbolDirectoryNet
     pub fn from_bytes(buf: &[u8]) -> Result<&SymbolDirectoryNet> {
         use std::mem;
         if buf.len() < mem::size_of::<SymbolDirectoryNet>() {
             return Err(Error::Eof);
         }
-        let res: &SymbolDirectoryNet = unsafe { mem::transmute(&buf[0]) };
+        if !(buf.as_ptr() as usize).is_multiple_of(mem::align_of::<SymbolDirectoryNet>()) {
+            return Err(Error::Message("unaligned SymbolDirectoryNet".to_owned()));
+        }
+        let res: &SymbolDirectoryNet = unsafe { &*(buf.as_ptr() as *const SymbolDirectoryNet) };
         Ok(res)
     }
+    /// Copy from buf of any alignment
+    pub fn read(buf: &[u8]) -> Result<SymbolDirectoryNet> {
+        use std::mem;
+        if buf.len() < mem::size_of::<SymbolDirectoryNet>() {
+            return Err(Error::Eof);
+        }
+        Ok(unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const SymbolDirectoryNet) })
+    }
     pub fn to_bytes(s: &SymbolDirectoryNet) -> Result<Vec<u8>> {
         use std::mem;
         const SD_LEN: usize = mem::size_of::<SymbolDirectoryNet>();
-        let res: &[u8; SD_LEN] = unsafe { mem::transmute(&s) };
+        let res = unsafe { &*(s as *const SymbolDirectoryNet as *const [u8; SD_LEN]) };
         Ok(res.to_vec())
     }
 }
@@ -92,15 +96,7 @@ pub struct SymbolTradingActionNet {
 
 impl SymbolTradingActionNet {
     pub fn state(&self) -> TradingState {
-        match self.trading_state {
-            b'H' => TradingState::Halted,
-            b'P' => TradingState::PreAuction,
-            b'A' => TradingState::Auction,
-            b'U' => TradingState::Paused,
-            b'C' => TradingState::Trading,
-            b'B' => TradingState::Break,
-            _ => todo!(),
-        }
+        TradingState::from(self.trading_state)
     }
 }
 
@@ -117,13 +113,7 @@ pub struct AddOrderNet {
 }
 
 fn bs_side(bs: u8) -> Side {
-    match bs {
-        b'B' => Side::Buy,
-        b'S' => Side::Sell,
-        b'C' => Side::BuyCover,
-        b'O' => Side::SellClose,
-        _ => todo!(),
-    }
+    Side::from(bs)
 }
 
 impl AddOrderNet {
@@ -191,16 +181,7 @@ impl OrderDeleteNet {
 }
 
 fn cancel_reason(r: u8) -> CancelReason {
-    match r {
-        b'U' => CancelReason::ByUser,
-        b'A' => CancelReason::Arb,
-        b'M' => CancelReason::ByModifyOrder,
-        b'O' => CancelReason::OddLot,
-        b'B' => CancelReason::OutOfPriceBand,
-        b'S' => CancelReason::BrokenSession,
-        b'N' => CancelReason::OutOfNormalTrading,
-        _ => todo!(),
-    }
+    CancelReason::from(r)
 }
 
 #[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
@@ -250,16 +231,57 @@ pub struct CrossTradeNet {
 
 impl CrossTradeNet {
     pub fn cross_type(&self) -> CrossType {
-        match self.type_ {
-            b'O' => CrossType::Opening,
-            b'C' => CrossType::Closing,
-            b'H' => CrossType::Halted,
-            b'I' => CrossType::Intraday,
-            _ => CrossType::Opening,
-        }
+        cross_type(self.type_)
     }
 }
 
+/// Market participant position of a member in an instrument
+#[derive(Deserialize, Serialize, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MarketParticipantNet {
+    pub tag: u8,
+    pub state: u8,
+    pub index: u16,
+    pub tracking: u16,
+    pub timestamp: u32,
+    pub member_id: u32,
+    pub primary_maker: bool,
+}
+
+impl MarketParticipantNet {
+    pub fn state(&self) -> MarketParticipantState {
+        MarketParticipantState::from(self.state)
+    }
+}
+
+/// Net Order Imbalance Indicator, during call auctions
+#[derive(Deserialize, Serialize, Default, Copy, Clone, PartialEq, Eq)]
+pub struct NoiiNet {
+    pub tag: u8,
+    pub direction: u8,
+    pub index: u16,
+    pub tracking: u16,
+    pub timestamp: u32,
+    pub paired_qty: u32,
+    pub imbalance_qty: u32,
+    pub indicative_price: i32,
+    pub near_price: i32,
+    pub far_price: i32,
+    pub type_: u8,
+}
+
+impl NoiiNet {
+    pub fn direction(&self) -> ImbalanceDirection {
+        ImbalanceDirection::from(self.direction)
+    }
+    pub fn cross_type(&self) -> CrossType {
+        cross_type(self.type_)
+    }
+}
+
+pub(super) fn cross_type(t: u8) -> CrossType {
+    CrossType::from(t)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,7 +289,7 @@ mod tests {
     #[test]
     fn test_event() {
         let mut ev: SystemEventNet = Default::default();
-        ev.event_code = EventCode::StartOfMessages as u8;
+        ev.event_code = u8::from(EventCode::StartOfMessages);
         assert!(EventCode::StartOfMessages == ev.event());
     }
 
@@ -284,30 +306,41 @@ mod tests {
     #[test]
     fn test_state() {
         let mut sym_tr: SymbolTradingActionNet = Default::default();
-        sym_tr.trading_state = TradingState::PreAuction as u8;
+        sym_tr.trading_state = u8::from(TradingState::PreAuction);
         assert_eq!(TradingState::PreAuction, sym_tr.state());
     }
 
     #[test]
     fn test_side() {
-        let bs = Side::BuyCover as u8;
+        let bs = u8::from(Side::BuyCover);
         assert_eq!(Side::BuyCover, bs_side(bs));
     }
 
     #[test]
     fn test_reason() {
-        let r = CancelReason::OddLot as u8;
+        let r = u8::from(CancelReason::OddLot);
         assert_eq!(CancelReason::OddLot, cancel_reason(r));
-        let r = CancelReason::OutOfPriceBand as u8;
+        let r = u8::from(CancelReason::OutOfPriceBand);
         assert_eq!(CancelReason::OutOfPriceBand, cancel_reason(r));
     }
 
+    #[test]
+    fn test_direction() {
+        let mut noii: NoiiNet = Default::default();
+        noii.direction = u8::from(ImbalanceDirection::InsufficientOrders);
+        assert_eq!(ImbalanceDirection::InsufficientOrders, noii.direction());
+        noii.direction = b'x';
+        assert_eq!(ImbalanceDirection::Unknown(b'x'), noii.direction());
+    }
+
     #[test]
     fn test_cross_type() {
         let mut cr: CrossTradeNet = Default::default();
-        cr.type_ = CrossType::Closing as u8;
+        cr.type_ = u8::from(CrossType::Closing);
         assert_eq!(CrossType::Closing, cr.cross_type());
-        cr.type_ = CrossType::Intraday as u8;
+        cr.type_ = u8::from(CrossType::Intraday);
         assert_eq!(CrossType::Intraday, cr.cross_type());
+        cr.type_ = b'x';
+        assert_eq!(CrossType::Unknown(b'x'), cr.cross_type());
     }
 }
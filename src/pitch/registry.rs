@@ -0,0 +1,240 @@
+//! registry - instruments announced by PITCH SymbolDirectory messages
+//!
+//! Maps instrument `index` to its record and symbol string to `index`,
+//! lookups do not allocate.
+
+use super::enums::{IssueClassification, MarketCategory};
+use super::pitch::{Body, Message, SymbolDirectory};
+use crate::PriceType;
+use std::collections::HashMap;
+use std::fmt;
+
+const SYMBOL_LEN: usize = 16;
+
+type SymbolKey = [u8; SYMBOL_LEN];
+
+/// Bytes of symbol kept in 16 bytes, truncated at a char boundary
+pub(super) fn symbol_len(symbol: &str) -> usize {
+    symbol
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|end| *end <= SYMBOL_LEN)
+        .last()
+        .unwrap_or(0)
+}
+
+fn symbol_key(symbol: &str) -> Option<SymbolKey> {
+    let sb = symbol.as_bytes();
+    if sb.len() > SYMBOL_LEN {
+        return None;
+    }
+    let mut key: SymbolKey = Default::default();
+    key[..sb.len()].copy_from_slice(sb);
+    Some(key)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    pub index: u16,
+    symbol: SymbolKey,
+    symbol_len: u8,
+    pub market_category: MarketCategory,
+    pub classification: IssueClassification,
+    pub precision: i8,
+    pub round_lot_size: u32,
+    pub turnover_multi: u32,
+    pub lower_limit: i32,
+    pub upper_limit: i32,
+    price_type: PriceType,
+}
+
+impl Instrument {
+    pub fn new(index: u16, s: &SymbolDirectory) -> Instrument {
+        let sb = s.symbol.as_bytes();
+        let ll = symbol_len(&s.symbol);
+        let mut symbol: SymbolKey = Default::default();
+        symbol[..ll].copy_from_slice(&sb[..ll]);
+        Instrument {
+            index,
+            symbol,
+            symbol_len: ll as u8,
+            market_category: MarketCategory::from(s.market_category),
+            classification: IssueClassification::from(s.classification),
+            precision: s.precision,
+            round_lot_size: s.round_lot_size,
+            turnover_multi: s.turnover_multi,
+            lower_limit: s.lower_limit,
+            upper_limit: s.upper_limit,
+            price_type: PriceType::new(s.precision),
+        }
+    }
+    pub fn symbol(&self) -> &str {
+        let sb = &self.symbol[..self.symbol_len as usize];
+        std::str::from_utf8(sb).unwrap_or_default()
+    }
+    /// price converter built from `precision`
+    pub fn price_type(&self) -> &PriceType {
+        &self.price_type
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "(index: {}, symbol: {}, market: {}, precision: {}, lot: {}, multi: {})",
+            self.index,
+            self.symbol(),
+            self.market_category,
+            self.precision,
+            self.round_lot_size,
+            self.turnover_multi
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct SymbolRegistry {
+    instruments: Vec<Option<Instrument>>,
+    by_symbol: HashMap<SymbolKey, u16>,
+    count: usize,
+}
+
+impl SymbolRegistry {
+    pub fn new() -> SymbolRegistry {
+        Default::default()
+    }
+    /// Register instrument if msg is a SymbolDirectory, return true if so
+    pub fn ingest(&mut self, msg: &Message) -> bool {
+        if let Body::SymbolDirectory(s) = &msg.body {
+            self.insert(Instrument::new(msg.index, s));
+            true
+        } else {
+            false
+        }
+    }
+    /// Add or replace instrument record of `inst.index`
+    pub fn insert(&mut self, inst: Instrument) {
+        let idx = inst.index as usize;
+        if self.instruments.len() <= idx {
+            self.instruments.resize(idx + 1, None);
+        }
+        if let Some(old) = &self.instruments[idx] {
+            // symbol may have been reassigned to another index since
+            if self.by_symbol.get(&old.symbol) == Some(&inst.index) {
+                self.by_symbol.remove(&old.symbol);
+            }
+        } else {
+            self.count += 1;
+        }
+        self.by_symbol.insert(inst.symbol, inst.index);
+        self.instruments[idx] = Some(inst);
+    }
+    pub fn get(&self, index: u16) -> Option<&Instrument> {
+        match self.instruments.get(index as usize) {
+            Some(inst) => inst.as_ref(),
+            None => None,
+        }
+    }
+    pub fn index_of(&self, symbol: &str) -> Option<u16> {
+        let key = symbol_key(symbol)?;
+        self.by_symbol.get(&key).copied()
+    }
+    pub fn lookup(&self, symbol: &str) -> Option<&Instrument> {
+        self.index_of(symbol).and_then(|idx| self.get(idx))
+    }
+    pub fn price_type(&self, index: u16) -> Option<&PriceType> {
+        self.get(index).map(|inst| inst.price_type())
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Instrument> {
+        self.instruments.iter().flatten()
+    }
+    pub fn len(&self) -> usize {
+        self.count
+    }
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+    pub fn clear(&mut self) {
+        self.instruments.clear();
+        self.by_symbol.clear();
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::from_bytes;
+
+    fn sym_dir(index: u16, symbol: &str, precision: i8) -> Message {
+        Message {
+            index,
+            tracking: 1,
+            timestamp: 0,
+            body: Body::SymbolDirectory(SymbolDirectory {
+                symbol: symbol.to_owned(),
+                market_category: b'H',
+                classification: b'F',
+                precision,
+                round_lot_size: 1,
+                turnover_multi: 5,
+                lower_limit: 60000,
+                upper_limit: 70000,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut reg = SymbolRegistry::new();
+        assert!(reg.ingest(&sym_dir(2, "cu1908", 0)));
+        assert!(reg.ingest(&sym_dir(5, "au1912", 2)));
+        assert_eq!(reg.len(), 2);
+        let inst = reg.lookup("au1912").unwrap();
+        assert_eq!(inst.index, 5);
+        assert_eq!(inst.market_category, MarketCategory::Shfe);
+        assert_eq!(inst.classification, IssueClassification::Futures);
+        assert_eq!(inst.price_type().to_string(123), "1.23");
+        assert_eq!(reg.index_of("cu1908"), Some(2));
+        assert!(reg.get(3).is_none());
+        assert!(reg.lookup("ag1912").is_none());
+        // replace symbol of index 2
+        reg.ingest(&sym_dir(2, "cu1909", 0));
+        assert_eq!(reg.len(), 2);
+        assert!(reg.index_of("cu1908").is_none());
+        assert_eq!(reg.get(2).unwrap().symbol(), "cu1909");
+        assert_eq!(reg.iter().count(), 2);
+        println!("instrument: {}", reg.get(2).unwrap());
+        // cu1909 moves to index 7, then index 2 is replaced
+        reg.ingest(&sym_dir(7, "cu1909", 0));
+        reg.ingest(&sym_dir(2, "cu1910", 0));
+        assert_eq!(reg.index_of("cu1909"), Some(7));
+        assert_eq!(reg.index_of("cu1910"), Some(2));
+    }
+
+    #[test]
+    fn test_symbol_truncate() {
+        let mut reg = SymbolRegistry::new();
+        // 15 ascii bytes then a 3 bytes char crossing byte 16
+        reg.ingest(&sym_dir(1, "abcdefghijklmno\u{4e2d}", 0));
+        assert_eq!(reg.get(1).unwrap().symbol(), "abcdefghijklmno");
+        reg.ingest(&sym_dir(2, "\u{4e2d}\u{6587}", 0));
+        assert_eq!(reg.get(2).unwrap().symbol(), "\u{4e2d}\u{6587}");
+    }
+
+    #[test]
+    fn test_registry_wire() {
+        let buf: Vec<u8> = vec![
+            b'R', 78, 99, 117, 49, 57, 48, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 70, 0, 2, 0, 3, 0, 98,
+            116, 140, 58, 5, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let msg = from_bytes(&buf[..]).unwrap();
+        let mut reg = SymbolRegistry::new();
+        assert!(reg.ingest(&msg));
+        let inst = reg.lookup("cu1908").unwrap();
+        assert_eq!(inst.index, 2);
+        assert_eq!(inst.market_category, MarketCategory::Unavailable);
+        assert_eq!(inst.classification, IssueClassification::Futures);
+    }
+}
@@ -0,0 +1,258 @@
+//! sequence - per instrument tracking number continuity check
+//!
+//! The tracking number of each instrument `index` is expected to grow by
+//! one per message, wrapping at u16. Missing ranges are kept as
+//! outstanding gaps until filled by late messages.
+
+use super::pitch::{peek_header, Message};
+use std::collections::HashMap;
+use std::fmt;
+
+// outstanding gaps kept per instrument, the oldest is dropped beyond
+const MAX_GAPS: usize = 64;
+
+/// Missing tracking numbers `start..=end` of instrument `index`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Gap {
+    pub index: u16,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Gap {
+    /// Number of missing messages
+    pub fn count(&self) -> usize {
+        self.end.wrapping_sub(self.start) as usize + 1
+    }
+    pub fn contains(&self, tracking: u16) -> bool {
+        tracking.wrapping_sub(self.start) <= self.end.wrapping_sub(self.start)
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "(index: {}, missing tracking {}..={})",
+            self.index, self.start, self.end
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeqStatus {
+    /// first message seen of the instrument
+    First,
+    InSequence,
+    /// messages skipped before this one
+    Gap(Gap),
+    /// late message filling an outstanding gap
+    OutOfOrder,
+    /// already seen or too old to be in any outstanding gap
+    Duplicate,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SeqStats {
+    pub messages: u64,
+    pub gaps: u64,
+    pub missing: u64,
+    pub out_of_order: u64,
+    pub duplicates: u64,
+}
+
+#[derive(Default)]
+struct Track {
+    next: u16,
+    gaps: Vec<Gap>,
+}
+
+impl Track {
+    // remove tracking from outstanding gaps, true if it was missing
+    fn fill(&mut self, tracking: u16) -> bool {
+        let pos = match self.gaps.iter().position(|g| g.contains(tracking)) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let g = self.gaps[pos];
+        if g.start == g.end {
+            self.gaps.remove(pos);
+        } else if tracking == g.start {
+            self.gaps[pos].start = tracking.wrapping_add(1);
+        } else if tracking == g.end {
+            self.gaps[pos].end = tracking.wrapping_sub(1);
+        } else {
+            self.gaps[pos].end = tracking.wrapping_sub(1);
+            let tail = Gap {
+                index: g.index,
+                start: tracking.wrapping_add(1),
+                end: g.end,
+            };
+            self.gaps.insert(pos + 1, tail);
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct SeqChecker {
+    tracks: HashMap<u16, Track>,
+    stats: SeqStats,
+}
+
+impl SeqChecker {
+    pub fn new() -> SeqChecker {
+        Default::default()
+    }
+    pub fn check(&mut self, msg: &Message) -> SeqStatus {
+        self.check_tracking(msg.index, msg.tracking)
+    }
+    /// Check raw message bytes, None if no header could be peeked
+    pub fn check_bytes(&mut self, buf: &[u8]) -> Option<SeqStatus> {
+        let (index, tracking, _) = peek_header(buf)?;
+        Some(self.check_tracking(index, tracking))
+    }
+    pub fn check_tracking(&mut self, index: u16, tracking: u16) -> SeqStatus {
+        self.stats.messages += 1;
+        let trk = match self.tracks.get_mut(&index) {
+            Some(trk) => trk,
+            None => {
+                let next = tracking.wrapping_add(1);
+                let gaps = Vec::new();
+                self.tracks.insert(index, Track { next, gaps });
+                return SeqStatus::First;
+            }
+        };
+        let diff = tracking.wrapping_sub(trk.next) as i16;
+        if diff == 0 {
+            trk.next = tracking.wrapping_add(1);
+            SeqStatus::InSequence
+        } else if diff > 0 {
+            let gap = Gap {
+                index,
+                start: trk.next,
+                end: tracking.wrapping_sub(1),
+            };
+            if trk.gaps.len() >= MAX_GAPS {
+                trk.gaps.remove(0);
+            }
+            trk.gaps.push(gap);
+            trk.next = tracking.wrapping_add(1);
+            self.stats.gaps += 1;
+            self.stats.missing += gap.count() as u64;
+            SeqStatus::Gap(gap)
+        } else if trk.fill(tracking) {
+            self.stats.out_of_order += 1;
+            SeqStatus::OutOfOrder
+        } else {
+            self.stats.duplicates += 1;
+            SeqStatus::Duplicate
+        }
+    }
+    /// Next tracking number expected of instrument
+    pub fn expected(&self, index: u16) -> Option<u16> {
+        self.tracks.get(&index).map(|t| t.next)
+    }
+    /// Gaps not filled yet of instrument
+    pub fn gaps(&self, index: u16) -> &[Gap] {
+        match self.tracks.get(&index) {
+            Some(t) => &t.gaps,
+            None => &[],
+        }
+    }
+    /// All gaps not filled yet
+    pub fn outstanding(&self) -> impl Iterator<Item = &Gap> {
+        self.tracks.values().flat_map(|t| t.gaps.iter())
+    }
+    pub fn stats(&self) -> &SeqStats {
+        &self.stats
+    }
+    /// Forget instrument, next message will be treated as First
+    pub fn reset(&mut self, index: u16) {
+        self.tracks.remove(&index);
+    }
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.stats = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence() {
+        let mut sc = SeqChecker::new();
+        assert_eq!(sc.check_tracking(1, 1), SeqStatus::First);
+        assert_eq!(sc.check_tracking(1, 2), SeqStatus::InSequence);
+        assert_eq!(sc.check_tracking(2, 100), SeqStatus::First);
+        let gap = Gap {
+            index: 1,
+            start: 3,
+            end: 6,
+        };
+        assert_eq!(sc.check_tracking(1, 7), SeqStatus::Gap(gap));
+        assert_eq!(gap.count(), 4);
+        assert_eq!(sc.expected(1), Some(8));
+        assert_eq!(sc.check_tracking(1, 4), SeqStatus::OutOfOrder);
+        assert_eq!(sc.gaps(1).len(), 2);
+        assert_eq!(sc.check_tracking(1, 4), SeqStatus::Duplicate);
+        assert_eq!(sc.check_tracking(1, 3), SeqStatus::OutOfOrder);
+        assert_eq!(sc.check_tracking(1, 7), SeqStatus::Duplicate);
+        assert_eq!(sc.gaps(1)[0].start, 5);
+        assert_eq!(sc.gaps(1)[0].end, 6);
+        assert_eq!(sc.outstanding().count(), 1);
+        let st = sc.stats();
+        assert_eq!(st.messages, 8);
+        assert_eq!(st.gaps, 1);
+        assert_eq!(st.missing, 4);
+        assert_eq!(st.out_of_order, 2);
+        assert_eq!(st.duplicates, 2);
+    }
+
+    #[test]
+    fn test_sequence_wrap() {
+        let mut sc = SeqChecker::new();
+        sc.check_tracking(3, 65534);
+        assert_eq!(sc.check_tracking(3, 65535), SeqStatus::InSequence);
+        assert_eq!(sc.check_tracking(3, 0), SeqStatus::InSequence);
+        match sc.check_tracking(3, 2) {
+            SeqStatus::Gap(g) => {
+                assert_eq!((g.start, g.end), (1, 1));
+                println!("gap: {}", g);
+            }
+            _ => panic!("expect gap"),
+        }
+        let gap = Gap {
+            index: 3,
+            start: 65530,
+            end: 2,
+        };
+        assert!(gap.contains(65535));
+        assert!(gap.contains(0));
+        assert!(!gap.contains(3));
+        assert_eq!(gap.count(), 9);
+    }
+
+    #[test]
+    fn test_check_bytes() {
+        use crate::pitch::{to_bytes, Body, TradingAction, TradingState};
+        let mut sc = SeqChecker::new();
+        for (tracking, reason) in [(1, 0xffff), (2, 0), (4, 0x0900)] {
+            let msg = Message {
+                index: 9,
+                tracking,
+                timestamp: 0,
+                body: Body::TradingAction(TradingAction {
+                    trading_state: TradingState::Trading,
+                    reason,
+                }),
+            };
+            assert!(sc.check_bytes(&to_bytes(&msg).unwrap()).is_some());
+        }
+        assert_eq!(sc.expected(9), Some(5));
+        assert_eq!(sc.gaps(9).len(), 1);
+        assert_eq!(sc.check_bytes(b"Z"), None);
+    }
+}
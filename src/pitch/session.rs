@@ -0,0 +1,413 @@
+//! session - per instrument trading state machine
+//!
+//! Follows TradingAction messages of each instrument `index` and market
+//! wide emergency SystemEvents. Transitions outside the session lifecycle
+//! are still applied, as the feed is authoritative, but reported.
+
+use super::clock::MsgClock;
+use super::enums::{EventCode, TradingState};
+use super::pitch::{Body, Message};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Reason of the latest state of an instrument
+///
+/// `TradingAction.reason` codes 0..=5 are decoded, others kept as Unknown
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ActionReason {
+    /// 0, session schedule
+    Scheduled,
+    /// 1, price limit up or down reached
+    PriceLimit,
+    /// 2, volatility interruption, circuit breaker
+    Volatility,
+    /// 3, suspended by regulator or exchange
+    Regulatory,
+    /// 4, exchange system failure
+    SystemFailure,
+    /// 5, news pending from issuer
+    NewsPending,
+    Unknown(u16),
+    /// set by market wide emergency SystemEvent, not sent on the wire
+    Emergency,
+}
+
+impl From<u16> for ActionReason {
+    fn from(v: u16) -> ActionReason {
+        match v {
+            0 => ActionReason::Scheduled,
+            1 => ActionReason::PriceLimit,
+            2 => ActionReason::Volatility,
+            3 => ActionReason::Regulatory,
+            4 => ActionReason::SystemFailure,
+            5 => ActionReason::NewsPending,
+            _ => ActionReason::Unknown(v),
+        }
+    }
+}
+
+impl fmt::Display for ActionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionReason::Scheduled => write!(f, "Scheduled"),
+            ActionReason::PriceLimit => write!(f, "Price Limit"),
+            ActionReason::Volatility => write!(f, "Volatility"),
+            ActionReason::Regulatory => write!(f, "Regulatory"),
+            ActionReason::SystemFailure => write!(f, "System Failure"),
+            ActionReason::NewsPending => write!(f, "News Pending"),
+            ActionReason::Unknown(c) => write!(f, "Unknown({})", c),
+            ActionReason::Emergency => write!(f, "Emergency"),
+        }
+    }
+}
+
+/// Whether the session lifecycle allows from -> to, repeating the same
+/// state is allowed
+pub fn is_legal_transition(from: TradingState, to: TradingState) -> bool {
+    use TradingState::*;
+    match (from, to) {
+        (Unknown(_), _) | (_, Unknown(_)) => false,
+        (a, b) if a == b => true,
+        (_, Halted) => true,
+        (PreAuction, Auction | Paused) => true,
+        (Auction, Trading | Break) => true,
+        (Trading, Break | Paused | PreAuction) => true,
+        (Break, Trading | PreAuction | Auction) => true,
+        (Paused, Trading | PreAuction | Auction) => true,
+        (Halted, PreAuction | Auction | Trading | Paused) => true,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InstrumentState {
+    pub state: TradingState,
+    pub reason: ActionReason,
+    /// micros since epoch of entering state
+    pub since: u64,
+    /// illegal transitions seen
+    pub illegal: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StateEvent {
+    /// from None for the first state of the instrument
+    Transition {
+        index: u16,
+        from: Option<TradingState>,
+        to: TradingState,
+        reason: ActionReason,
+    },
+    /// transition not allowed by the session lifecycle, state applied
+    Illegal {
+        index: u16,
+        from: TradingState,
+        to: TradingState,
+        reason: ActionReason,
+    },
+    /// all instruments halted
+    EmergencyHalt { halted: usize },
+    /// all instruments paused, quotes only
+    EmergencyQuoteOnly { paused: usize },
+    /// states before emergency restored, followed by a Transition of
+    /// each instrument restored
+    EmergencyResumption { resumed: usize },
+}
+
+impl fmt::Display for StateEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateEvent::Transition {
+                index,
+                from: Some(from),
+                to,
+                reason,
+            } => write!(f, "(index: {}, {} -> {}, {})", index, from, to, reason),
+            StateEvent::Transition {
+                index, to, reason, ..
+            } => write!(f, "(index: {}, -> {}, {})", index, to, reason),
+            StateEvent::Illegal {
+                index,
+                from,
+                to,
+                reason,
+            } => write!(
+                f,
+                "(index: {}, illegal {} -> {}, {})",
+                index, from, to, reason
+            ),
+            StateEvent::EmergencyHalt { halted } => write!(f, "Emergency Halt of {}", halted),
+            StateEvent::EmergencyQuoteOnly { paused } => {
+                write!(f, "Emergency QuoteOnly of {}", paused)
+            }
+            StateEvent::EmergencyResumption { resumed } => {
+                write!(f, "Emergency Resumption of {}", resumed)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct StateTracker {
+    clock: MsgClock,
+    states: HashMap<u16, InstrumentState>,
+    // states before emergency, restored on resumption of instruments
+    // without TradingAction since
+    saved: Option<HashMap<u16, InstrumentState>>,
+}
+
+impl StateTracker {
+    /// Tracker with hour base hours until the first SystemEvent
+    pub fn new(hours: u32) -> StateTracker {
+        StateTracker {
+            clock: MsgClock::new(hours),
+            ..Default::default()
+        }
+    }
+    pub fn state(&self, index: u16) -> Option<&InstrumentState> {
+        self.states.get(&index)
+    }
+    /// Micros in current state as of the latest message
+    pub fn time_in_state(&self, index: u16) -> Option<u64> {
+        let st = self.states.get(&index)?;
+        Some(self.clock.micros().saturating_sub(st.since))
+    }
+    pub fn is_emergency(&self) -> bool {
+        self.saved.is_some()
+    }
+    /// Instruments with a known state
+    pub fn instruments(&self) -> impl Iterator<Item = (u16, &InstrumentState)> + '_ {
+        self.states.iter().map(|(k, v)| (*k, v))
+    }
+    /// Apply one PITCH message, append state events to events
+    pub fn update(&mut self, msg: &Message, events: &mut Vec<StateEvent>) {
+        let now = self.clock.update(msg);
+        match &msg.body {
+            Body::TradingAction(s) => {
+                let reason = ActionReason::from(s.reason);
+                events.push(self.transition(msg.index, s.trading_state, reason, now));
+            }
+            Body::SystemEvent(s) => self.system_event(s.event, now, events),
+            _ => (),
+        }
+    }
+    fn transition(
+        &mut self,
+        index: u16,
+        to: TradingState,
+        reason: ActionReason,
+        now: u64,
+    ) -> StateEvent {
+        let st = match self.states.get_mut(&index) {
+            Some(st) => st,
+            None => {
+                let st = InstrumentState {
+                    state: to,
+                    reason,
+                    since: now,
+                    illegal: 0,
+                };
+                self.states.insert(index, st);
+                return StateEvent::Transition {
+                    index,
+                    from: None,
+                    to,
+                    reason,
+                };
+            }
+        };
+        let from = st.state;
+        if from != to {
+            st.since = now;
+        }
+        st.state = to;
+        st.reason = reason;
+        if is_legal_transition(from, to) {
+            StateEvent::Transition {
+                index,
+                from: Some(from),
+                to,
+                reason,
+            }
+        } else {
+            st.illegal += 1;
+            StateEvent::Illegal {
+                index,
+                from,
+                to,
+                reason,
+            }
+        }
+    }
+    // all instruments to state, saving states unless in emergency already
+    fn set_all(&mut self, to: TradingState, now: u64) -> usize {
+        if self.saved.is_none() {
+            self.saved = Some(self.states.clone());
+        }
+        for st in self.states.values_mut() {
+            if st.state != to {
+                st.since = now;
+            }
+            st.state = to;
+            st.reason = ActionReason::Emergency;
+        }
+        self.states.len()
+    }
+    fn system_event(&mut self, event: EventCode, now: u64, events: &mut Vec<StateEvent>) {
+        match event {
+            EventCode::EmergencyHalt => {
+                let halted = self.set_all(TradingState::Halted, now);
+                events.push(StateEvent::EmergencyHalt { halted });
+            }
+            EventCode::EmergencyQuoteOnly => {
+                let paused = self.set_all(TradingState::Paused, now);
+                events.push(StateEvent::EmergencyQuoteOnly { paused });
+            }
+            EventCode::EmergencyResumption => {
+                let saved = match self.saved.take() {
+                    Some(saved) => saved,
+                    None => return,
+                };
+                let mut restored: Vec<(u16, InstrumentState)> = saved
+                    .into_iter()
+                    .filter(|(index, _)| {
+                        // TradingAction during emergency is authoritative
+                        self.states
+                            .get(index)
+                            .is_some_and(|st| st.reason == ActionReason::Emergency)
+                    })
+                    .collect();
+                restored.sort_by_key(|(index, _)| *index);
+                events.push(StateEvent::EmergencyResumption {
+                    resumed: restored.len(),
+                });
+                for (index, mut st) in restored {
+                    st.since = now;
+                    let old = self.states.insert(index, st);
+                    events.push(StateEvent::Transition {
+                        index,
+                        from: old.map(|o| o.state),
+                        to: st.state,
+                        reason: st.reason,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::TradingAction;
+    use crate::testutil::{system_event, HOURS};
+
+    fn action(index: u16, timestamp: u32, state: TradingState, reason: u16) -> Message {
+        Message {
+            index,
+            tracking: 0,
+            timestamp,
+            body: Body::TradingAction(TradingAction {
+                trading_state: state,
+                reason,
+            }),
+        }
+    }
+
+    // events of one message
+    fn update(tr: &mut StateTracker, msg: &Message) -> Vec<StateEvent> {
+        let mut events = Vec::new();
+        tr.update(msg, &mut events);
+        events
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        use TradingState::*;
+        let mut tr = StateTracker::new(HOURS);
+        let mut ts = 0;
+        for (i, st) in [PreAuction, Auction, Trading, Break, Trading, Halted]
+            .iter()
+            .enumerate()
+        {
+            ts = i as u32 * 1_000_000;
+            let ev = update(&mut tr, &action(1, ts, *st, 0));
+            assert!(matches!(ev[..], [StateEvent::Transition { to, .. }] if to == *st));
+        }
+        let st = tr.state(1).unwrap();
+        assert_eq!((st.state, st.illegal), (Halted, 0));
+        update(&mut tr, &action(2, ts + 500_000, Trading, 0));
+        assert_eq!(tr.time_in_state(1), Some(500_000));
+        // no break from halted
+        let ev = update(&mut tr, &action(1, ts + 600_000, Break, 1));
+        println!("{}", ev[0]);
+        assert_eq!(
+            ev,
+            vec![StateEvent::Illegal {
+                index: 1,
+                from: Halted,
+                to: Break,
+                reason: ActionReason::PriceLimit,
+            }]
+        );
+        assert_eq!(tr.state(1).unwrap().illegal, 1);
+        assert_eq!(tr.state(1).unwrap().state, Break);
+        let ev = update(&mut tr, &action(1, ts + 700_000, Unknown(b'?'), 0));
+        assert!(matches!(ev[..], [StateEvent::Illegal { .. }]));
+        assert!(update(&mut tr, &system_event(EventCode::StartOfMarketHours, ts)).is_empty());
+    }
+
+    #[test]
+    fn test_emergency() {
+        use TradingState::*;
+        let mut tr = StateTracker::new(HOURS);
+        update(&mut tr, &action(1, 1000, Trading, 0));
+        update(&mut tr, &action(2, 2000, Auction, 0));
+        update(&mut tr, &action(3, 2500, Trading, 7));
+        let ev = update(&mut tr, &system_event(EventCode::EmergencyHalt, 3000));
+        assert_eq!(ev, vec![StateEvent::EmergencyHalt { halted: 3 }]);
+        assert!(tr.is_emergency());
+        assert_eq!(tr.state(2).unwrap().state, Halted);
+        assert_eq!(tr.state(2).unwrap().reason, ActionReason::Emergency);
+        update(&mut tr, &system_event(EventCode::EmergencyQuoteOnly, 4000));
+        assert_eq!(tr.state(1).unwrap().state, Paused);
+        // instrument 3 moved on during emergency, kept on resumption
+        update(&mut tr, &action(3, 5000, Halted, 9));
+        let ev = update(&mut tr, &system_event(EventCode::EmergencyResumption, 9000));
+        assert_eq!(
+            ev,
+            vec![
+                StateEvent::EmergencyResumption { resumed: 2 },
+                StateEvent::Transition {
+                    index: 1,
+                    from: Some(Paused),
+                    to: Trading,
+                    reason: ActionReason::Scheduled,
+                },
+                StateEvent::Transition {
+                    index: 2,
+                    from: Some(Paused),
+                    to: Auction,
+                    reason: ActionReason::Scheduled,
+                },
+            ]
+        );
+        assert!(!tr.is_emergency());
+        assert_eq!(tr.state(1).unwrap().state, Trading);
+        assert_eq!(tr.state(2).unwrap().state, Auction);
+        assert_eq!(tr.time_in_state(2), Some(0));
+        let st = tr.state(3).unwrap();
+        assert_eq!((st.state, st.reason), (Halted, ActionReason::Unknown(9)));
+        assert!(update(&mut tr, &system_event(EventCode::EmergencyResumption, 9000)).is_empty());
+    }
+
+    #[test]
+    fn test_reason() {
+        assert_eq!(ActionReason::from(1), ActionReason::PriceLimit);
+        assert_eq!(ActionReason::from(5), ActionReason::NewsPending);
+        assert_eq!(ActionReason::from(0x0102), ActionReason::Unknown(0x0102));
+        assert_eq!(ActionReason::Volatility.to_string(), "Volatility");
+        assert_eq!(ActionReason::Unknown(7).to_string(), "Unknown(7)");
+    }
+}
@@ -0,0 +1,510 @@
+//! view - zero-copy views of PITCH wire messages
+//!
+//! A view borrows the raw bytes of a message, e.g. from `MdCache` shared
+//! memory, and reads fields at their wire offsets on access. Fields are
+//! read as little endian bytes, so buffers need no alignment.
+
+use super::enums::{
+    CancelReason, CrossType, EventCode, ImbalanceDirection, IssueClassification,
+    MarketParticipantState, Side, TradingState,
+};
+use super::handler::{PitchHeader, SymbolDirectoryRef};
+use super::proto::cross_type;
+use crate::serde::{Error, Result};
+
+fn get_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn get_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn get_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+macro_rules! view {
+    ($(#[$m:meta])* $name:ident, $tag:literal, $len:literal, $idx:literal, $ts:literal) => {
+        $(#[$m])*
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        pub struct $name<'a>(&'a [u8]);
+
+        impl<'a> $name<'a> {
+            pub const TAG: u8 = $tag;
+            /// Bytes of message on wire
+            pub const LEN: usize = $len;
+            pub fn new(buf: &'a [u8]) -> Result<$name<'a>> {
+                match buf.first() {
+                    None => return Err(Error::Eof),
+                    Some(&$tag) => (),
+                    Some(c) => {
+                        return Err(Error::UnknownCode {
+                            field: "tag",
+                            code: *c,
+                            offset: 0,
+                        })
+                    }
+                }
+                if buf.len() < $len {
+                    return Err(Error::Eof);
+                }
+                Ok($name(&buf[..$len]))
+            }
+            pub fn as_bytes(&self) -> &'a [u8] {
+                self.0
+            }
+            pub fn index(&self) -> u16 {
+                get_u16(self.0, $idx)
+            }
+            pub fn tracking(&self) -> u16 {
+                get_u16(self.0, $idx + 2)
+            }
+            /// Microseconds since Hours
+            pub fn timestamp(&self) -> u32 {
+                get_u32(self.0, $ts)
+            }
+            pub fn header(&self) -> PitchHeader {
+                PitchHeader {
+                    index: self.index(),
+                    tracking: self.tracking(),
+                    timestamp: self.timestamp(),
+                }
+            }
+        }
+    };
+}
+
+view!(SystemEventView, b'S', 14, 2, 10);
+view!(SymbolDirectoryView, b'R', 44, 20, 24);
+view!(TradingActionView, b'H', 12, 4, 8);
+view!(AddOrderView, b'A', 26, 2, 6);
+view!(OrderExecutedView, b'E', 30, 2, 6);
+view!(OrderExecutedWithPriceView, b'C', 34, 2, 6);
+view!(OrderCancelView, b'X', 22, 2, 6);
+view!(OrderDeleteView, b'D', 18, 2, 6);
+view!(OrderReplaceView, b'U', 33, 1, 5);
+view!(TradeView, b'P', 34, 2, 6);
+view!(CrossTradeView, b'Q', 34, 2, 6);
+view!(ImbalanceView, b'I', 31, 2, 6);
+view!(MarketParticipantView, b'L', 15, 2, 6);
+
+impl SystemEventView<'_> {
+    pub fn event(&self) -> EventCode {
+        EventCode::from(self.0[1])
+    }
+    /// hours since Unix Epoch
+    pub fn time_hours(&self) -> u32 {
+        get_u32(self.0, 6)
+    }
+}
+
+impl<'a> SymbolDirectoryView<'a> {
+    pub fn market_category(&self) -> u8 {
+        self.0[1]
+    }
+    /// Symbol bytes, zero padding trimmed
+    pub fn symbol_bytes(&self) -> &'a [u8] {
+        let sym = &self.0[2..18];
+        let ll = sym.iter().rposition(|c| *c != 0).map_or(0, |p| p + 1);
+        &sym[..ll]
+    }
+    pub fn symbol(&self) -> Result<&'a str> {
+        std::str::from_utf8(self.symbol_bytes()).map_err(|_| Error::Syntax)
+    }
+    pub fn classification(&self) -> u8 {
+        self.0[18]
+    }
+    pub fn issue_classification(&self) -> IssueClassification {
+        IssueClassification::from(self.0[18])
+    }
+    pub fn precision(&self) -> i8 {
+        self.0[19] as i8
+    }
+    pub fn lot_size(&self) -> u32 {
+        get_u32(self.0, 28)
+    }
+    pub fn turnover_multi(&self) -> u32 {
+        get_u32(self.0, 32)
+    }
+    pub fn lower_limit(&self) -> i32 {
+        get_u32(self.0, 36) as i32
+    }
+    pub fn upper_limit(&self) -> i32 {
+        get_u32(self.0, 40) as i32
+    }
+    pub fn to_ref(&self) -> Result<SymbolDirectoryRef<'a>> {
+        Ok(SymbolDirectoryRef {
+            symbol: self.symbol()?,
+            market_category: self.market_category(),
+            classification: self.classification(),
+            precision: self.precision(),
+            round_lot_size: self.lot_size(),
+            turnover_multi: self.turnover_multi(),
+            lower_limit: self.lower_limit(),
+            upper_limit: self.upper_limit(),
+        })
+    }
+}
+
+impl TradingActionView<'_> {
+    pub fn trading_state(&self) -> TradingState {
+        TradingState::from(self.0[1])
+    }
+    pub fn reason(&self) -> u16 {
+        get_u16(self.0, 2)
+    }
+}
+
+impl AddOrderView<'_> {
+    pub fn side(&self) -> Side {
+        Side::from(self.0[1])
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 18)
+    }
+    pub fn price(&self) -> i32 {
+        get_u32(self.0, 22) as i32
+    }
+}
+
+impl OrderExecutedView<'_> {
+    pub fn printable(&self) -> bool {
+        self.0[1] != 0
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 18)
+    }
+    pub fn match_no(&self) -> u64 {
+        get_u64(self.0, 22)
+    }
+}
+
+impl OrderExecutedWithPriceView<'_> {
+    pub fn printable(&self) -> bool {
+        self.0[1] != 0
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 18)
+    }
+    pub fn match_no(&self) -> u64 {
+        get_u64(self.0, 22)
+    }
+    pub fn price(&self) -> i32 {
+        get_u32(self.0, 30) as i32
+    }
+}
+
+impl OrderCancelView<'_> {
+    pub fn reason(&self) -> CancelReason {
+        CancelReason::from(self.0[1])
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 18)
+    }
+}
+
+impl OrderDeleteView<'_> {
+    pub fn reason(&self) -> CancelReason {
+        CancelReason::from(self.0[1])
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+}
+
+impl OrderReplaceView<'_> {
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 9)
+    }
+    pub fn new_ref_no(&self) -> u64 {
+        get_u64(self.0, 17)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 25)
+    }
+    pub fn price(&self) -> i32 {
+        get_u32(self.0, 29) as i32
+    }
+}
+
+impl TradeView<'_> {
+    pub fn side(&self) -> Side {
+        Side::from(self.0[1])
+    }
+    pub fn ref_no(&self) -> u64 {
+        get_u64(self.0, 10)
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 18)
+    }
+    pub fn price(&self) -> i32 {
+        get_u32(self.0, 22) as i32
+    }
+    pub fn match_no(&self) -> u64 {
+        get_u64(self.0, 26)
+    }
+}
+
+impl CrossTradeView<'_> {
+    pub fn cross_type(&self) -> CrossType {
+        cross_type(self.0[1])
+    }
+    pub fn qty(&self) -> u32 {
+        get_u32(self.0, 10)
+    }
+    pub fn price(&self) -> i32 {
+        get_u32(self.0, 14) as i32
+    }
+    pub fn pclose(&self) -> i32 {
+        get_u32(self.0, 18) as i32
+    }
+    pub fn open_interest(&self) -> u32 {
+        get_u32(self.0, 22)
+    }
+    pub fn match_no(&self) -> u64 {
+        get_u64(self.0, 26)
+    }
+}
+
+impl ImbalanceView<'_> {
+    pub fn direction(&self) -> ImbalanceDirection {
+        ImbalanceDirection::from(self.0[1])
+    }
+    pub fn paired_qty(&self) -> u32 {
+        get_u32(self.0, 10)
+    }
+    pub fn imbalance_qty(&self) -> u32 {
+        get_u32(self.0, 14)
+    }
+    pub fn indicative_price(&self) -> i32 {
+        get_u32(self.0, 18) as i32
+    }
+    pub fn near_price(&self) -> i32 {
+        get_u32(self.0, 22) as i32
+    }
+    pub fn far_price(&self) -> i32 {
+        get_u32(self.0, 26) as i32
+    }
+    pub fn cross_type(&self) -> CrossType {
+        cross_type(self.0[30])
+    }
+}
+
+impl MarketParticipantView<'_> {
+    pub fn state(&self) -> MarketParticipantState {
+        MarketParticipantState::from(self.0[1])
+    }
+    pub fn member_id(&self) -> u32 {
+        get_u32(self.0, 10)
+    }
+    pub fn primary_maker(&self) -> bool {
+        self.0[14] != 0
+    }
+}
+
+/// View of any PITCH message
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageView<'a> {
+    SystemEvent(SystemEventView<'a>),
+    SymbolDirectory(SymbolDirectoryView<'a>),
+    TradingAction(TradingActionView<'a>),
+    AddOrder(AddOrderView<'a>),
+    OrderExecuted(OrderExecutedView<'a>),
+    OrderExecutedWithPrice(OrderExecutedWithPriceView<'a>),
+    OrderCancelled(OrderCancelView<'a>),
+    OrderDelete(OrderDeleteView<'a>),
+    ReplaceOrder(OrderReplaceView<'a>),
+    Trade(TradeView<'a>),
+    CrossTrade(CrossTradeView<'a>),
+    Imbalance(ImbalanceView<'a>),
+    MarketParticipant(MarketParticipantView<'a>),
+}
+
+impl<'a> MessageView<'a> {
+    /// View of raw message by its tag, trailing bytes ignored
+    pub fn new(buf: &'a [u8]) -> Result<MessageView<'a>> {
+        let vv = match *buf.first().ok_or(Error::Eof)? {
+            b'S' => MessageView::SystemEvent(SystemEventView::new(buf)?),
+            b'R' => MessageView::SymbolDirectory(SymbolDirectoryView::new(buf)?),
+            b'H' => MessageView::TradingAction(TradingActionView::new(buf)?),
+            b'A' => MessageView::AddOrder(AddOrderView::new(buf)?),
+            b'E' => MessageView::OrderExecuted(OrderExecutedView::new(buf)?),
+            b'C' => MessageView::OrderExecutedWithPrice(OrderExecutedWithPriceView::new(buf)?),
+            b'X' => MessageView::OrderCancelled(OrderCancelView::new(buf)?),
+            b'D' => MessageView::OrderDelete(OrderDeleteView::new(buf)?),
+            b'U' => MessageView::ReplaceOrder(OrderReplaceView::new(buf)?),
+            b'P' => MessageView::Trade(TradeView::new(buf)?),
+            b'Q' => MessageView::CrossTrade(CrossTradeView::new(buf)?),
+            b'I' => MessageView::Imbalance(ImbalanceView::new(buf)?),
+            b'L' => MessageView::MarketParticipant(MarketParticipantView::new(buf)?),
+            tag => {
+                return Err(Error::UnknownCode {
+                    field: "tag",
+                    code: tag,
+                    offset: 0,
+                })
+            }
+        };
+        Ok(vv)
+    }
+    pub fn header(&self) -> PitchHeader {
+        match self {
+            MessageView::SystemEvent(v) => v.header(),
+            MessageView::SymbolDirectory(v) => v.header(),
+            MessageView::TradingAction(v) => v.header(),
+            MessageView::AddOrder(v) => v.header(),
+            MessageView::OrderExecuted(v) => v.header(),
+            MessageView::OrderExecutedWithPrice(v) => v.header(),
+            MessageView::OrderCancelled(v) => v.header(),
+            MessageView::OrderDelete(v) => v.header(),
+            MessageView::ReplaceOrder(v) => v.header(),
+            MessageView::Trade(v) => v.header(),
+            MessageView::CrossTrade(v) => v.header(),
+            MessageView::Imbalance(v) => v.header(),
+            MessageView::MarketParticipant(v) => v.header(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::*;
+    use crate::ClMessage;
+
+    fn msg(body: Body) -> Message {
+        Message {
+            index: 513,
+            tracking: 1027,
+            timestamp: 123456789,
+            body,
+        }
+    }
+
+    #[test]
+    fn test_views() {
+        let msgs = [
+            msg(Body::SystemEvent(SystemEvent {
+                event: EventCode::StartOfMarketHours,
+                time_hours: 480_000,
+            })),
+            msg(Body::SymbolDirectory(SymbolDirectory {
+                symbol: "cu2409".to_owned(),
+                market_category: b'H',
+                classification: b'F',
+                precision: 2,
+                round_lot_size: 5,
+                turnover_multi: 5,
+                lower_limit: -100,
+                upper_limit: 900_000,
+            })),
+            msg(Body::TradingAction(TradingAction {
+                trading_state: TradingState::Halted,
+                reason: 77,
+            })),
+            msg(Body::OrderExecutedWithPrice(OrderExecutedWithPrice {
+                printable: true,
+                reference: 1 << 40,
+                qty: 7,
+                price: -3,
+                match_no: 99,
+            })),
+            msg(Body::ReplaceOrder(ReplaceOrder {
+                old_reference: 1,
+                new_reference: 2,
+                qty: 3,
+                price: 4,
+            })),
+            msg(Body::CrossTrade(CrossTrade {
+                qty: 10,
+                price: 20,
+                match_no: 30,
+                cross_type: CrossType::Closing,
+                pclose: 40,
+                open_interest: 50,
+            })),
+            msg(Body::Imbalance(Imbalance {
+                paired_qty: 10,
+                imbalance_qty: 3,
+                direction: ImbalanceDirection::Buy,
+                indicative_price: 100,
+                near_price: 101,
+                far_price: 99,
+                cross_type: CrossType::Closing,
+            })),
+            msg(Body::MarketParticipant(MarketParticipant {
+                member_id: 88,
+                primary_maker: false,
+                state: MarketParticipantState::Excused,
+            })),
+        ];
+        for m in &msgs {
+            let buf = to_bytes(m).unwrap();
+            // misaligned by ClMessage len prefix
+            let cl = ClMessage::new(&buf);
+            let vv = MessageView::new(cl.data()).unwrap();
+            assert_eq!(vv.header(), PitchHeader::from(m));
+            assert_eq!(peek_header(&buf), Some((513, 1027, 123456789)));
+            match (vv, &m.body) {
+                (MessageView::SystemEvent(v), Body::SystemEvent(s)) => {
+                    assert_eq!((v.event(), v.time_hours()), (s.event, s.time_hours));
+                }
+                (MessageView::SymbolDirectory(v), Body::SymbolDirectory(s)) => {
+                    assert_eq!(v.symbol().unwrap(), "cu2409");
+                    assert_eq!(v.issue_classification(), IssueClassification::Futures);
+                    assert_eq!(SymbolDirectory::from(&v.to_ref().unwrap()), *s);
+                }
+                (MessageView::TradingAction(v), Body::TradingAction(s)) => {
+                    assert_eq!((v.trading_state(), v.reason()), (s.trading_state, 77));
+                }
+                (MessageView::OrderExecutedWithPrice(v), Body::OrderExecutedWithPrice(s)) => {
+                    assert!(v.printable());
+                    assert_eq!((v.ref_no(), v.qty()), (s.reference, s.qty));
+                    assert_eq!((v.price(), v.match_no()), (s.price, s.match_no));
+                }
+                (MessageView::ReplaceOrder(v), Body::ReplaceOrder(s)) => {
+                    assert_eq!((v.ref_no(), v.new_ref_no()), (1, 2));
+                    assert_eq!((v.qty(), v.price()), (s.qty, s.price));
+                }
+                (MessageView::CrossTrade(v), Body::CrossTrade(s)) => {
+                    assert_eq!(v.cross_type(), s.cross_type);
+                    assert_eq!((v.qty(), v.price(), v.match_no()), (10, 20, 30));
+                    assert_eq!((v.pclose(), v.open_interest()), (40, 50));
+                }
+                (MessageView::Imbalance(v), Body::Imbalance(s)) => {
+                    assert_eq!((v.direction(), v.cross_type()), (s.direction, s.cross_type));
+                    assert_eq!((v.paired_qty(), v.imbalance_qty()), (10, 3));
+                    assert_eq!(v.indicative_price(), s.indicative_price);
+                    assert_eq!((v.near_price(), v.far_price()), (101, 99));
+                }
+                (MessageView::MarketParticipant(v), Body::MarketParticipant(s)) => {
+                    assert_eq!((v.state(), v.member_id()), (s.state, 88));
+                    assert!(!v.primary_maker());
+                }
+                _ => panic!("view of wrong type"),
+            }
+        }
+        let buf = to_bytes(&msgs[3]).unwrap();
+        assert!(matches!(
+            AddOrderView::new(&buf),
+            Err(Error::UnknownCode { .. })
+        ));
+        assert!(matches!(
+            OrderExecutedWithPriceView::new(&buf[..20]),
+            Err(Error::Eof)
+        ));
+    }
+}
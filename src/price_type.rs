@@ -25,7 +25,7 @@ fn digit_multi(ndig: i8) -> f64 {
     dMulti[(ndig + 2) as usize]
 }
 
-#[derive(Eq, Clone, Default)]
+#[derive(Debug, Eq, Clone, Default)]
 pub struct PriceType(i8);
 
 impl PartialEq for PriceType {
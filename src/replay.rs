@@ -0,0 +1,245 @@
+//! replay - historical replay of a series driven by simulated SysClock
+//!
+//! Absolute time of each message is rebuilt by MsgClock, the simulated
+//! clock is set to it before the message is dispatched to subscribers.
+//! Pacing is against the wall clock from the first message replayed.
+
+use crate::mdcache::{Series, TimeIndex};
+use crate::pitch::{from_bytes_mode, timeval_micros, DecodeMode, Message, MsgClock};
+use crate::{nsleep, SysClock, TimeVal};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Speed {
+    AsFast,
+    RealTime,
+    /// N times of real time
+    Factor(f64),
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub messages: u64,
+    /// messages failed to decode
+    pub skipped: u64,
+    /// micros of series time replayed
+    pub span_micros: u64,
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "messages: {}, skipped: {}, span: {}.{:06}s",
+            self.messages,
+            self.skipped,
+            self.span_micros / 1_000_000,
+            self.span_micros % 1_000_000
+        )
+    }
+}
+
+type Subscriber<'s> = Box<dyn FnMut(&Message, &TimeVal) + 's>;
+
+pub struct Replay<'s, S: Series + ?Sized> {
+    series: &'s S,
+    clock: SysClock,
+    msg_clock: MsgClock,
+    speed: Speed,
+    mode: DecodeMode,
+    pos: usize,
+    // wall and series micros of first message paced
+    origin: Option<(u64, u64)>,
+    first_us: Option<u64>,
+    subs: Vec<Subscriber<'s>>,
+    stats: ReplayStats,
+}
+
+impl<'s, S: Series + ?Sized> Replay<'s, S> {
+    /// Replay series from start, hour base before first SystemEvent
+    /// taken from init_time of series
+    pub fn new(series: &'s S, speed: Speed) -> Replay<'s, S> {
+        let hours = TimeVal::new(series.header().init_time as u64, 0).as_hours();
+        Replay {
+            series,
+            clock: SysClock::new(true),
+            msg_clock: MsgClock::new(hours),
+            speed,
+            mode: DecodeMode::Strict,
+            pos: 0,
+            origin: None,
+            first_us: None,
+            subs: Vec::new(),
+            stats: Default::default(),
+        }
+    }
+    pub fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.mode = mode;
+    }
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.origin = None;
+    }
+    /// Call f with each message and its absolute time
+    pub fn subscribe<F: FnMut(&Message, &TimeVal) + 's>(&mut self, f: F) {
+        self.subs.push(Box::new(f));
+    }
+    /// Continue from first message at or after tv
+    pub fn seek_time(&mut self, tidx: &TimeIndex, tv: &TimeVal) {
+        self.pos = tidx.seek_time(self.series.published(), tv);
+        self.msg_clock = MsgClock::at(timeval_micros(tv));
+        self.origin = None;
+        // SysClock never moves back once set
+        self.clock = SysClock::new(true);
+    }
+    /// Position of the next message
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// Simulated clock, set to time of each message dispatched unless
+    /// already past it, runs with the wall clock in between
+    pub fn clock(&self) -> &SysClock {
+        &self.clock
+    }
+    pub fn stats(&self) -> &ReplayStats {
+        &self.stats
+    }
+    // wait until wall clock catches up with series time us
+    fn pace(&mut self, us: u64) {
+        let factor = match self.speed {
+            Speed::AsFast => return,
+            Speed::RealTime => 1.0,
+            Speed::Factor(f) if f > 0.0 => f,
+            Speed::Factor(_) => return,
+        };
+        let wall = timeval_micros(&TimeVal::now());
+        let (wall0, us0) = *self.origin.get_or_insert((wall, us));
+        let target = wall0 + (us.saturating_sub(us0) as f64 / factor) as u64;
+        if target > wall {
+            nsleep((target - wall) * 1000);
+        }
+    }
+    /// Replay next message, return its time or None at end of series
+    pub fn step(&mut self) -> Option<TimeVal> {
+        let msgs = self.series.published();
+        while self.pos < msgs.len() {
+            let buf = msgs[self.pos].data();
+            self.pos += 1;
+            let msg = match from_bytes_mode(buf, self.mode) {
+                Ok(msg) => msg,
+                Err(_) => {
+                    self.stats.skipped += 1;
+                    continue;
+                }
+            };
+            let us = self.msg_clock.update(&msg);
+            self.pace(us);
+            let tv = self.msg_clock.timeval();
+            self.clock.set_timeval(&tv);
+            for f in self.subs.iter_mut() {
+                f(&msg, &tv);
+            }
+            let us0 = *self.first_us.get_or_insert(us);
+            self.stats.span_micros = us.saturating_sub(us0);
+            self.stats.messages += 1;
+            return Some(tv);
+        }
+        None
+    }
+    /// Replay to end of series
+    pub fn run(&mut self) -> &ReplayStats {
+        while self.step().is_some() {}
+        &self.stats
+    }
+    /// Replay messages up to time tv
+    pub fn run_until(&mut self, tv: &TimeVal) -> &ReplayStats {
+        let until = timeval_micros(tv);
+        let msgs = self.series.published();
+        while self.pos < msgs.len() {
+            let mut clk = self.msg_clock;
+            match clk.update_bytes(msgs[self.pos].data()) {
+                Some(us) if us > until => break,
+                _ => (),
+            }
+            self.step();
+        }
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::EventCode;
+    use crate::testutil::{add_orders, cl_message, system_event, VecSeries, HOURS};
+    use crate::ClMessage;
+    use std::time::Instant;
+
+    // a message per step micros from start of hour
+    fn series(cnt: u32, step: u32) -> VecSeries {
+        let mut msgs = vec![cl_message(&system_event(EventCode::StartOfMarketHours, 0))];
+        msgs.extend(add_orders(1..cnt, |i| (1, i as u16, i * step)));
+        msgs.push(ClMessage::new(b"Zbad"));
+        VecSeries::new(msgs)
+    }
+
+    #[test]
+    fn test_replay_fast() {
+        let ss = series(1000, 1_000_000);
+        let mut times = Vec::new();
+        let mut rp = Replay::new(&ss, Speed::AsFast);
+        rp.subscribe(|msg, tv| times.push((msg.tracking, *tv)));
+        let st = *rp.run();
+        println!("replay: {}", st);
+        assert_eq!(st.messages, 1000);
+        assert_eq!(st.skipped, 1);
+        assert_eq!(st.span_micros, 999_000_000);
+        let last = TimeVal::from_hours(HOURS) + 999_000_000_000;
+        assert!(rp.clock().now() >= last);
+        drop(rp);
+        assert_eq!(times.len(), 1000);
+        assert_eq!(times[10].0, 10);
+        assert!(times[10].1 == TimeVal::from_hours(HOURS) + 10_000_000_000);
+    }
+
+    #[test]
+    fn test_replay_paced() {
+        // 50 messages over 49ms of series time
+        let ss = series(50, 1000);
+        let mut rp = Replay::new(&ss, Speed::RealTime);
+        let t0 = Instant::now();
+        rp.run();
+        assert!(t0.elapsed().as_micros() >= 49_000);
+        // 10 times faster
+        let ss = series(101, 1000);
+        let mut rp = Replay::new(&ss, Speed::Factor(10.0));
+        let mut cnt = 0;
+        rp.subscribe(|_, _| cnt += 1);
+        let t0 = Instant::now();
+        let until = TimeVal::from_hours(HOURS) + 50_000_000;
+        assert_eq!(rp.run_until(&until).messages, 51);
+        let el = t0.elapsed().as_micros();
+        assert!(el >= 5_000, "elapsed {}us", el);
+        assert_eq!(rp.position(), 51);
+        rp.run();
+        drop(rp);
+        assert_eq!(cnt, 101);
+    }
+
+    #[test]
+    fn test_replay_seek() {
+        let ss = series(1000, 1_000_000);
+        let tidx = TimeIndex::build(ss.published(), 0, 64);
+        let mut rp = Replay::new(&ss, Speed::AsFast);
+        let tv = TimeVal::from_hours(HOURS) + 500_500_000_000;
+        rp.seek_time(&tidx, &tv);
+        assert_eq!(rp.position(), 501);
+        let t1 = rp.step().unwrap();
+        assert!(t1 == TimeVal::from_hours(HOURS) + 501_000_000_000);
+        // clock follows seek back
+        let tv = TimeVal::from_hours(HOURS) + 100_000_000_000;
+        rp.seek_time(&tidx, &tv);
+        let t2 = rp.step().unwrap();
+        assert!(rp.clock().now() >= t2 && rp.clock().now() < t1);
+    }
+}
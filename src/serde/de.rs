@@ -51,6 +51,15 @@ where
     }
 }
 
+// Deserialize from the head of input, trailing bytes are ignored
+pub fn from_bytes_prefix<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes(s);
+    T::deserialize(&mut deserializer)
+}
+
 // SERDE IS NOT A PARSING LIBRARY. This impl block defines a few basic parsing
 // functions from scratch. More complicated formats may wish to use a dedicated
 // parsing library to help implement their Serde deserializer.
@@ -29,6 +29,12 @@ pub enum Error {
     ExpectedString,
     ExpectedArray,
     TrailingCharacters,
+    // code byte not defined by the protocol, for field at offset of input
+    UnknownCode {
+        field: &'static str,
+        code: u8,
+        offset: usize,
+    },
 }
 
 impl ser::Error for Error {
@@ -55,6 +61,15 @@ impl Display for Error {
             Error::ExpectedString => formatter.write_str("expect string input"),
             Error::ExpectedArray => formatter.write_str("expect array input"),
             Error::TrailingCharacters => formatter.write_str("trailing chars"),
+            Error::UnknownCode {
+                field,
+                code,
+                offset,
+            } => write!(
+                formatter,
+                "unknown {} code 0x{:02x} at offset {}",
+                field, code, offset
+            ),
             /* and so forth */
             //_ => todo!(),
         }
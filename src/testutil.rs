@@ -0,0 +1,86 @@
+//! testutil - fixtures shared by unit tests
+//!
+//! PITCH series built in memory, their hour base and unique names of
+//! series created on /dev/shm.
+
+use crate::mdcache::{MdHeader, Series};
+use crate::pitch::{to_bytes, AddOrder, Body, EventCode, Message, Side, SystemEvent};
+use crate::ClMessage;
+
+/// Hour base of series, 2024-10-04 00:00 UTC
+pub(crate) const HOURS: u32 = 480_000;
+
+/// Series name unique to process, stale series of an aborted run left on
+/// /dev/shm do not fail create
+pub(crate) fn test_series_name(prefix: &str) -> String {
+    format!("{}_{}.bin", prefix, std::process::id())
+}
+
+pub(crate) fn cl_message(msg: &Message) -> ClMessage {
+    ClMessage::new(&to_bytes(msg).unwrap())
+}
+
+/// SystemEvent of hour base HOURS
+pub(crate) fn system_event(event: EventCode, timestamp: u32) -> Message {
+    Message {
+        index: 0,
+        tracking: 1,
+        timestamp,
+        body: Body::SystemEvent(SystemEvent {
+            event,
+            time_hours: HOURS,
+        }),
+    }
+}
+
+/// AddOrder of reference i for each i, f gives (index, tracking,
+/// timestamp) of i
+pub(crate) fn add_orders<I, F>(iter: I, f: F) -> Vec<ClMessage>
+where
+    I: IntoIterator<Item = u32>,
+    F: Fn(u32) -> (u16, u16, u32),
+{
+    iter.into_iter()
+        .map(|i| {
+            let (index, tracking, timestamp) = f(i);
+            cl_message(&Message {
+                index,
+                tracking,
+                timestamp,
+                body: Body::AddOrder(AddOrder {
+                    reference: i as u64,
+                    side: Side::Buy,
+                    qty: 1,
+                    price: 1,
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Series of messages in memory
+pub(crate) struct VecSeries {
+    pub header: MdHeader,
+    pub msgs: Vec<ClMessage>,
+}
+
+impl VecSeries {
+    pub fn new(msgs: Vec<ClMessage>) -> VecSeries {
+        VecSeries {
+            header: Default::default(),
+            msgs,
+        }
+    }
+}
+
+impl Series for VecSeries {
+    fn header(&self) -> &MdHeader {
+        &self.header
+    }
+    fn published(&self) -> &[ClMessage] {
+        &self.msgs
+    }
+    fn path(&self) -> &str {
+        ""
+    }
+}
@@ -23,6 +23,7 @@ pub struct TimeVal {
 #[derive(Default)]
 pub struct SysClock {
     sim: bool,
+    synced: bool,
     adj: TimeVal,
 }
 
@@ -100,7 +101,7 @@ impl Add for TimeVal {
         let sec = if nano < TS3_TIME_NANO {
             rhs.sec
         } else {
-            rhs.sec + 1
+            rhs.sec.wrapping_add(1)
         };
         let nano = if nano < TS3_TIME_NANO {
             nano
@@ -118,7 +119,7 @@ impl AddAssign for TimeVal {
         let sec = if nano < TS3_TIME_NANO {
             rhs.sec
         } else {
-            rhs.sec + 1
+            rhs.sec.wrapping_add(1)
         };
         self.nano = if nano < TS3_TIME_NANO {
             nano
@@ -174,7 +175,7 @@ impl Sub for TimeVal {
         } else {
             self.nano - rhs.nano
         };
-        let (sec, _) = u64_sub(self.sec, rhs.sec + cc);
+        let (sec, _) = u64_sub(self.sec, rhs.sec.wrapping_add(cc));
         TimeVal { sec, nano }
     }
 }
@@ -187,7 +188,7 @@ impl SubAssign for TimeVal {
         } else {
             self.nano - rhs.nano
         };
-        (self.sec, _) = u64_sub(self.sec, rhs.sec + cc);
+        (self.sec, _) = u64_sub(self.sec, rhs.sec.wrapping_add(cc));
     }
 }
 
@@ -301,10 +302,11 @@ impl SysClock {
         if !self.sim {
             panic!("Not Simulation Clock");
         }
-        let adj = *tv - self.now();
-        // no rollback time
-        if self.adj < adj {
-            self.adj = adj;
+        let now = self.now();
+        // no rollback time once set, adj wraps for time before now
+        if !self.synced || now < *tv {
+            self.adj += *tv - now;
+            self.synced = true;
         }
     }
 }